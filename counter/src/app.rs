@@ -1,6 +1,12 @@
 pub(crate) mod popup;
+pub(crate) mod command;
+pub(crate) mod registry;
+pub(crate) mod headless;
+mod state;
+mod transfer;
+mod profile;
+mod combined;
 
-use std::ops::{Index, IndexMut};
 use std::sync::Arc;
 use std::time::Duration;
 use eframe::{Frame, Storage};
@@ -10,24 +16,81 @@ use egui::ahash::HashMap;
 use egui_extras::Column;
 use serde::{Deserialize, Serialize};
 use tokio::time::Instant;
-use crate::counter_or_timer::CounterTimer;
+use crate::app::command::{Command, CommandError, Operation};
+use crate::counter_or_timer::{CounterId, CounterTimer};
 use crate::get_runtime;
+use crate::chat::{Chat, ChatConfig};
+use crate::server::{Server, ServerConfig};
+use crate::server::subathon::AdjustmentLog;
 
 const LINK_LATEST:&str = "https://github.com/C0D3-M4513R/time/releases/latest";
 const CURRENT_VERSION:&str = "-\tCurrent Version: v0.2.2";
 
-#[derive(Default, Deserialize, Serialize)]
+#[derive(Deserialize, Serialize)]
 pub(crate) struct App{
     next_name: String,
-    names: Vec<Arc<str>>,
-    counters: HashMap<Arc<str>, CounterTimer>,
+    ///Never reused, so that ids stay unique
+    #[serde(default)]
+    next_id: CounterId,
+    ///The order, in which the counters are shown
+    #[serde(default)]
+    order: Vec<CounterId>,
+    counters: HashMap<CounterId, CounterTimer>,
+    ///The name of the shown profile, which `order` and `counters` belong to
+    #[serde(default = "profile::default_profile")]
+    profile: String,
+    ///Every other profile
+    #[serde(default)]
+    profiles: profile::Profiles,
+    #[serde(default)]
+    server: ServerConfig,
+    #[serde(default)]
+    chat: ChatConfig,
+    #[serde(default)]
+    combined: combined::CombinedConfig,
+    ///Continue counters, that were running when the app was closed
+    #[serde(default)]
+    resume_on_launch: bool,
     #[serde(skip)]
     other_app_state: OtherAppState,
 }
+impl Default for App {
+    fn default() -> Self {
+        Self {
+            next_name: String::new(),
+            next_id: CounterId::default(),
+            order: Vec::new(),
+            counters: HashMap::default(),
+            profile: profile::default_profile(),
+            profiles: profile::Profiles::default(),
+            server: ServerConfig::default(),
+            chat: ChatConfig::default(),
+            combined: combined::CombinedConfig::default(),
+            resume_on_launch: false,
+            other_app_state: OtherAppState::default(),
+        }
+    }
+}
+
 #[derive(Default)]
 struct OtherAppState{
     popup: popup::ArcPopupStore,
+    commands: command::CommandChannel,
+    registry: registry::Registry,
+    server: Option<Server>,
+    server_open: bool,
+    subathon_log: AdjustmentLog,
+    chat: Option<Chat>,
+    chat_open: bool,
+    combined: Option<combined::Combined>,
+    combined_open: bool,
+    combined_pick: Option<(combined::Pick, tokio::task::JoinHandle<Option<rfd::FileHandle>>)>,
+    profiles_open: bool,
+    next_profile: String,
+    profile_err: bool,
     text_err: Option<(&'static str, Instant)>,
+    ///Names, that couldn't be applied yet, because another counter has them
+    renaming: HashMap<CounterId, String>,
 }
 
 impl App {
@@ -39,31 +102,83 @@ impl App {
         // Note that you must enable the `persistence` feature for this to work.
         let mut slf;
         if let Some(Some(state)) = cc.storage.map(|storage|storage.get_string(eframe::APP_KEY)) {
-            slf = serde_json::from_str(state.as_str()).unwrap_or_else(|err| {
-                let slf = Self::default();
-                log::error!("Failed deserialising App State. Will reset to the defaults. Error: {err}");
-                popup::handle_display_popup_arc(
-                    &slf.other_app_state.popup,
-                    "The App State has been reset to the defaults",
-                    &err,
-                    "Failed to Load App State."
-                );
-                slf
-            });
+            slf = Self::from_state(state.as_str());
         } else{
             log::info!("Either no storage source or no stored app state");
             slf = Self::default();
         }
 
-        for counter in slf.counters.values_mut(){
-            counter.popup = slf.other_app_state.popup.clone();
+        slf.other_app_state.commands.set_repaint(cc.egui_ctx.clone());
+        slf.init_counters();
+        slf.resume_counters();
+        slf
+    }
+
+    ///Continues the counters, that were running when the app was closed, if [`App::resume_on_launch`] is set.
+    fn resume_counters(&mut self){
+        let resume = self.resume_on_launch;
+        for (_, counter) in self.all_counters_mut(){
+            if resume {
+                counter.resume_counter();
+            } else {
+                counter.discard_resume();
+            }
         }
+    }
 
-        slf
+    ///Connects freshly deserialized counters to the rest of the app.
+    fn init_counters(&mut self){
+        let popup = self.other_app_state.popup.clone();
+        let commands = self.other_app_state.commands.sender();
+        let mut max = None;
+        for (id, counter) in self.all_counters_mut(){
+            counter.id = *id;
+            counter.popup = popup.clone();
+            counter.commands = commands.clone();
+            max = max.max(Some(*id));
+        }
+        if let Some(max) = max {
+            self.next_id = self.next_id.max(CounterId(max.0.saturating_add(1)));
+        }
+        self.repair_profiles();
+    }
+
+    fn name_taken(&self, name: &str, except: Option<CounterId>) -> bool {
+        self.counters.values().any(|counter| Some(counter.id) != except && counter.name.as_ref() == name)
+    }
+
+    ///Renames the counter, unless the name is taken. A taken name is kept in the edit field, until it's changed.
+    ///Returns whether the counter was renamed.
+    fn rename_counter(&mut self, id: CounterId, new_name: String) -> bool {
+        if self.name_taken(&new_name, Some(id)) {
+            self.other_app_state.renaming.insert(id, new_name);
+            return false;
+        }
+        self.other_app_state.renaming.remove(&id);
+        match self.counters.get_mut(&id) {
+            Some(counter) => {
+                counter.name = Arc::from(new_name);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn counter_by_name(&mut self, name: &str) -> Option<&mut CounterTimer> {
+        self.counters.values_mut().find(|counter| counter.name.as_ref() == name)
+    }
+
+    fn add_counter(&mut self, name: Arc<str>) -> CounterId {
+        let id = self.next_id;
+        self.next_id = CounterId(id.0.saturating_add(1));
+        let counter = CounterTimer::new(id, name, self.other_app_state.popup.clone(), self.other_app_state.commands.sender());
+        self.counters.insert(id, counter);
+        self.order.push(id);
+        id
     }
 
     fn save_custom(&self, storage: &mut dyn Storage) {
-        match serde_json::to_string(self){
+        match self.to_state(){
             Ok(state) => {
                 storage.set_string(eframe::APP_KEY, state);
                 log::debug!("Saved app state");
@@ -74,6 +189,186 @@ impl App {
         }
     }
 
+    fn process_commands(&mut self){
+        while let Some(command) = self.other_app_state.commands.try_recv() {
+            self.handle_command(command);
+        }
+    }
+
+    fn handle_command(&mut self, command: Command){
+        log::debug!("Processing command: {command:?}");
+        let result = self.apply_command(&command);
+        match command.reply {
+            Some(reply) => {
+                //The requester might have given up already. That's fine.
+                let _ = reply.send(result);
+            }
+            None => {
+                if let Err(err) = result {
+                    log::warn!("Failed to apply {:?} to Counter {}: {err}", command.operation, command.counter.as_ref());
+                    popup::handle_display_popup_arc(
+                        &self.other_app_state.popup,
+                        "A command for a Counter could not be applied",
+                        &err,
+                        "Command failed"
+                    );
+                }
+            }
+        }
+    }
+
+    ///Stops the counters of every profile and waits, until all of them wrote their last outputs.
+    ///Only for shutting down. Counters of hidden profiles were kept running on purpose.
+    fn stop_all(&mut self){
+        //Stopping first lets all counters finish at the same time
+        for (_, counter) in self.all_counters_mut(){
+            counter.stop_counter();
+        }
+        for (_, counter) in self.all_counters_mut(){
+            counter.wait_stopped();
+        }
+    }
+
+    ///Stops the counters of the shown profile.
+    fn stop_shown(&mut self){
+        for counter in self.counters.values_mut(){
+            counter.stop_counter();
+        }
+    }
+
+    ///Returns the nanoseconds, that were added to the counter.
+    fn apply_command(&mut self, command: &Command) -> Result<i64, CommandError>{
+        let counter = self.counter_by_name(&command.counter)
+            .ok_or_else(|| CommandError::UnknownCounter(command.counter.clone()))?;
+        //The amounts are nanoseconds, which would be taken as a huge count
+        if matches!(command.operation, Operation::Add(_) | Operation::Set(_) | Operation::AddCapped { .. }) && !counter.is_timed() {
+            return Err(CommandError::NotTimed(command.counter.clone()));
+        }
+        let added = match command.operation {
+            Operation::Start => {
+                counter.start_counter();
+                0
+            }
+            Operation::Stop => {
+                counter.stop_counter();
+                0
+            }
+            Operation::Reset => {
+                counter.reset_counter();
+                0
+            }
+            Operation::Add(nanos) => counter.add_time(nanos).then_some(nanos)
+                .ok_or_else(|| CommandError::NotAdjustable(command.counter.clone()))?,
+            Operation::Set(nanos) => counter.set_time(nanos).then_some(0)
+                .ok_or_else(|| CommandError::NotAdjustable(command.counter.clone()))?,
+            //Capped here, where commands are applied one after another, so that a burst of adds can't overshoot.
+            Operation::AddCapped { nanos, max } => counter.add_time_capped(nanos, max)
+                .ok_or_else(|| CommandError::NotAdjustable(command.counter.clone()))?,
+        };
+        Ok(added)
+    }
+
+    ///Makes the counters of the shown profile visible outside the ui thread. Hidden profiles are not published.
+    fn publish(&mut self){
+        for counter in self.counters.values_mut(){
+            counter.sync_status();
+        }
+        let counters = self.order.iter()
+            .filter_map(|id| self.counters.get(id))
+            .map(CounterTimer::subscribe)
+            .collect();
+        self.other_app_state.registry.publish(counters);
+    }
+
+    ///Starts, stops or restarts the http server to match the config.
+    fn update_server(&mut self){
+        match &self.other_app_state.server {
+            Some(server) if self.server.enabled && server.port() == self.server.port => {
+                server.set_config(&self.server);
+            }
+            _ => {
+                //Dropping the old server shuts it down
+                self.other_app_state.server = None;
+                if self.server.enabled {
+                    self.other_app_state.server = Some(Server::start(
+                        &self.server,
+                        self.other_app_state.registry.clone(),
+                        self.other_app_state.commands.sender(),
+                        self.other_app_state.subathon_log.clone(),
+                        self.other_app_state.popup.clone(),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn update_chat(&mut self){
+        match &self.other_app_state.chat {
+            Some(chat) if self.chat.enabled && chat.set_config(&self.chat) => {}
+            _ => {
+                //Dropping the old client disconnects it
+                self.other_app_state.chat = None;
+                if self.chat.enabled {
+                    self.other_app_state.chat = Some(Chat::start(
+                        &self.chat,
+                        self.other_app_state.commands.sender(),
+                        self.other_app_state.popup.clone(),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn chat_window(&mut self, ctx: &Context){
+        let mut open = self.other_app_state.chat_open;
+        egui::Window::new("Chat Commands")
+            .open(&mut open)
+            .show(ctx, |ui|{
+                self.chat.ui(ui, self.other_app_state.chat.as_ref());
+            });
+        self.other_app_state.chat_open = open;
+    }
+
+    fn server_window(&mut self, ctx: &Context){
+        let mut open = self.other_app_state.server_open;
+        egui::Window::new("Http Server")
+            .open(&mut open)
+            .show(ctx, |ui|{
+                ui.checkbox(&mut self.server.enabled, "Enabled");
+                ui.horizontal(|ui|{
+                    ui.label("Port:");
+                    egui::DragValue::new(&mut self.server.port).ui(ui);
+                });
+                let base = format!("http://127.0.0.1:{}", self.server.port);
+                match &self.other_app_state.server {
+                    Some(server) if server.is_running() => {
+                        ui.hyperlink_to(format!("Listening on {base}"), &base);
+                    }
+                    Some(_) => {
+                        ui.colored_label(ui.visuals().error_fg_color, "Not running. Check the popups for errors.");
+                    }
+                    None => {
+                        ui.label("Not running.");
+                    }
+                }
+                ui.horizontal(|ui|{
+                    ui.label("Bearer Token:");
+                    ui.add(egui::TextEdit::singleline(&mut self.server.token).password(true).hint_text("none"));
+                }).response.on_hover_text("Required to start, stop or change counters over http. Leave empty to allow everyone on this pc.");
+                ui.label("Control counters with POST requests to /api/counters/<name>/start, stop, reset, add or set. Add and set take a json body like {\"seconds\": 300}.");
+                ui.label("Overlays for OBS Browser Sources:");
+                for counter in self.order.iter().filter_map(|id| self.counters.get(id)) {
+                    ui.label(format!("{base}/overlay/{}", counter.name.as_ref()));
+                }
+                ui.label("Custom CSS for the overlays. The value is in #counter. body has data-counter, data-mode and data-running attributes.");
+                ui.code_editor(&mut self.server.css);
+                egui::CollapsingHeader::new("Subathon Webhook").show(ui, |ui|{
+                    self.server.subathon.ui(ui, &self.other_app_state.subathon_log);
+                });
+            });
+        self.other_app_state.server_open = open;
+    }
+
     fn display_popups(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame){
         let old_popup = core::mem::take(
             //Speed: there should never be a long lock on the popups. (only to push basically)
@@ -99,19 +394,25 @@ impl App {
 
 impl eframe::App for App{
     fn update(&mut self, ctx: &Context, frame: &mut Frame) {
-        ctx.request_repaint_after(crate::PERIOD);
-        let default_fn= |name|{
-            CounterTimer::new(name, self.other_app_state.popup.clone())
-        };
+        self.process_commands();
+        self.publish();
+        self.update_server();
+        self.update_chat();
+        self.update_combined();
+        ctx.request_repaint_after(
+            self.counters.values()
+                .filter_map(CounterTimer::repaint_after)
+                .fold(crate::PERIOD, Duration::min)
+        );
         egui::CentralPanel::default().show(ctx, |ui|{
             ui.horizontal(|ui|{
+                self.profile_switcher(ui);
                 let text_resp = ui.text_edit_singleline(&mut self.next_name);
                 if ui.button("Add new Counter").clicked(){
                     let name:Arc<str> = Arc::from(core::mem::take(&mut self.next_name));
-                    if !self.names.contains(&name) {
+                    if !self.name_taken(&name, None) {
                         self.other_app_state.text_err = None;
-                        self.names.push(name.clone());
-                        self.counters.insert(name.clone(), default_fn(name));
+                        self.add_counter(name);
                     }else{
                         self.other_app_state.text_err = Some(("This name is already taken. Please provide a uniqe name.", Instant::now()));
                     }
@@ -133,6 +434,28 @@ impl eframe::App for App{
                         i.stop_counter();
                     }
                 }
+                if ui.button("Reset Everything").clicked(){
+                    for i in self.counters.values_mut(){
+                        i.reset_counter();
+                    }
+                }
+                if ui.button("Export…").clicked(){
+                    self.export_counters();
+                }
+                if ui.button("Import…").clicked(){
+                    self.import_counters();
+                }
+                if ui.button("Http Server").clicked(){
+                    self.other_app_state.server_open = !self.other_app_state.server_open;
+                }
+                if ui.button("Chat Commands").clicked(){
+                    self.other_app_state.chat_open = !self.other_app_state.chat_open;
+                }
+                if ui.button("Combined Output").clicked(){
+                    self.other_app_state.combined_open = !self.other_app_state.combined_open;
+                }
+                ui.checkbox(&mut self.resume_on_launch, "Resume on launch")
+                    .on_hover_text("Counters, that were running when the app was closed or crashed, continue as if they never stopped.");
             });
             ui.with_layout(Layout::bottom_up(Align::Min), |ui|{
                 ui.horizontal(|ui|{
@@ -152,32 +475,31 @@ impl eframe::App for App{
                         })
                         .body(|body|{
                             body.rows(
-                                65.,
-                                self.names.len(),
+                                112.,
+                                self.order.len(),
                                 |mut row|{
                                         let index = row.index();
                                         let mut deleted = false;
-                                        if index < self.names.len() {
-                                            //all index ops should be valid here
+                                        if let Some(id) = self.order.get(index).copied() {
                                             row.col(|ui|{
-                                                let name = self.names.index_mut(index);
-                                                let mut new_name: String = name.as_ref().into();
-                                                if ui.text_edit_singleline(&mut new_name).changed() {
-                                                    let mut counter = self.counters.remove(&*name).unwrap_or_else(|| default_fn(name.clone()));
-                                                    let new_name: Arc<str> = Arc::from(new_name);
-                                                    counter.name = new_name.clone();
-                                                    self.counters.insert(new_name.clone(), counter);
-                                                    *name = new_name;
+                                                let name = self.counters.get(&id).map(|counter| counter.name.clone()).unwrap_or_default();
+                                                let mut new_name = self.other_app_state.renaming.remove(&id).unwrap_or_else(|| name.to_string());
+                                                ui.text_edit_singleline(&mut new_name);
+                                                if new_name != name.as_ref() && !self.rename_counter(id, new_name) {
+                                                    ui.colored_label(ui.visuals().error_fg_color, "This name is already taken.");
                                                 }
                                                 if ui.button("Delete").clicked(){
                                                     deleted = true;
-                                                    self.counters.remove(&self.names.remove(index));
+                                                    self.order.remove(index);
+                                                    self.counters.remove(&id);
+                                                    self.other_app_state.renaming.remove(&id);
                                                 }
                                             });
                                             if !deleted {
                                                 row.col(|ui|{
-                                                    let name = self.names.index(index);
-                                                    self.counters.entry(name.clone()).or_insert_with(||default_fn(name.clone())).ui(ui);
+                                                    if let Some(counter) = self.counters.get_mut(&id) {
+                                                        counter.ui(ui);
+                                                    }
                                                 });
                                             } else {
                                                 ctx.request_repaint();
@@ -193,6 +515,18 @@ impl eframe::App for App{
                 })
             });
         });
+        if self.other_app_state.server_open {
+            self.server_window(ctx);
+        }
+        if self.other_app_state.chat_open {
+            self.chat_window(ctx);
+        }
+        if self.other_app_state.combined_open {
+            self.combined_window(ctx);
+        }
+        if self.other_app_state.profiles_open {
+            self.profiles_window(ctx);
+        }
         self.display_popups(ctx, frame)
     }
 
@@ -200,12 +534,81 @@ impl eframe::App for App{
         self.save_custom(storage)
     }
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
-        for (_, counter) in self.counters.iter_mut(){
-            counter.stop_counter();
-        }
+        self.stop_all();
     }
 
     fn auto_save_interval(&self) -> Duration {
         Duration::from_secs(15)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(counter: &str, operation: Operation) -> Command {
+        Command { counter: Arc::from(counter), operation, reply: None }
+    }
+
+    #[test]
+    fn no_time_for_tallies() {
+        let mut app = App::parse_state(r#"{"version": 1, "next_name": "", "next_id": 1, "order": [0], "counters": {"0": {"name": "Deaths", "mode": "Tally", "outputs": []}}}"#).unwrap();
+        for operation in [Operation::Add(60_000_000_000), Operation::Set(0), Operation::AddCapped { nanos: 1, max: 2 }] {
+            assert_eq!(app.apply_command(&command("Deaths", operation)), Err(CommandError::NotTimed(Arc::from("Deaths"))));
+        }
+        assert_eq!(app.apply_command(&command("Deaths", Operation::Reset)), Ok(0));
+        assert_eq!(app.apply_command(&command("Lives", Operation::Reset)), Err(CommandError::UnknownCounter(Arc::from("Lives"))));
+    }
+
+    ///A Timer with 10 minutes, that was started a minute before the app was closed.
+    fn running_timer(resume: bool) -> App {
+        let since = (chrono::Utc::now() - chrono::Duration::seconds(60)).to_rfc3339();
+        let mut app = App::parse_state(&format!(r#"{{"version": 1, "next_name": "", "next_id": 1, "order": [0], "resume_on_launch": {resume}, "counters": {{"0": {{
+            "name": "Break", "mode": "Timer", "outputs": [], "period": {{"secs": 60, "nanos": 0}}, "initial_ns": 600000000000, "time_ns": 600000000000,
+            "running": {{"since": "{since}", "start_ns": 600000000000, "restarts_left": 0}}
+        }}}}}}"#)).unwrap();
+        app.init_counters();
+        app
+    }
+
+    #[test]
+    fn resume_on_launch() {
+        let _runtime = crate::get_runtime().enter();
+        let mut app = running_timer(true);
+        app.resume_counters();
+        let counter = app.counter_by_name("Break").unwrap();
+        assert!(counter.is_running());
+        //The minute while the app was closed counts
+        counter.stop_counter();
+        counter.sync_status();
+        let left = counter.subscribe().borrow().value_ns;
+        assert!((539_000_000_000..=540_000_000_000).contains(&left), "{left}");
+    }
+
+    #[test]
+    fn discard_without_resume_on_launch() {
+        let mut app = running_timer(false);
+        app.resume_counters();
+        let counter = app.counter_by_name("Break").unwrap();
+        assert!(!counter.is_running());
+        //Starting later doesn't pick up the old run either
+        assert!(!counter.resume_counter());
+        counter.sync_status();
+        assert_eq!(counter.subscribe().borrow().value_ns, 600_000_000_000);
+    }
+
+    #[test]
+    fn rename_to_taken_name() {
+        let mut app = App::default();
+        let break_id = app.add_counter(Arc::from("Break"));
+        let stream_id = app.add_counter(Arc::from("Stream"));
+        assert!(!app.rename_counter(stream_id, "Break".to_string()));
+        assert_eq!(app.counters[&stream_id].name.as_ref(), "Stream");
+        //The rejected name stays in the edit field
+        assert_eq!(app.other_app_state.renaming.get(&stream_id).map(String::as_str), Some("Break"));
+        assert!(app.rename_counter(stream_id, "Intermission".to_string()));
+        assert_eq!(app.counters[&stream_id].name.as_ref(), "Intermission");
+        assert!(app.other_app_state.renaming.is_empty());
+        //Keeping the own name is fine
+        assert!(app.rename_counter(break_id, "Break".to_string()));
+    }
+}
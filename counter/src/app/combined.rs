@@ -0,0 +1,331 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use chrono::{DateTime, Utc};
+use rfd::FileHandle;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use crate::app::popup::{handle_display_popup_arc, ArcPopupStore};
+use crate::app::registry::Registry;
+use crate::app::App;
+use crate::counter_or_timer::format::{parse_tokens, TemplateError, Token};
+use crate::counter_or_timer::output::{replace_file, ErrorThrottle, WriteStatus};
+use crate::counter_or_timer::Status;
+use crate::get_runtime;
+
+///One file for several counters, and a json file with the state of every counter.
+///Both only cover the counters of the shown profile, like the http server and the chat.
+///Counters of other profiles are left empty, until their profile is shown.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(default)]
+pub(crate) struct CombinedConfig {
+    pub enabled: bool,
+    ///Where the rendered `template` goes. Empty means it isn't written.
+    pub file: PathBuf,
+    ///`{name}` is replaced with the text of the counter called name
+    pub template: String,
+    ///Where the json status goes. Empty means it isn't written.
+    pub status_file: PathBuf,
+}
+
+///A parsed [`CombinedConfig::template`]. The fields are counter names.
+struct CombinedTemplate(Vec<Token<String>>);
+
+impl FromStr for CombinedTemplate {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_tokens(s, Ok).map(Self)
+    }
+}
+
+impl CombinedTemplate {
+    fn counters(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(|part| match part {
+            Token::Field(name) => Some(name.as_str()),
+            Token::Literal(_) => None,
+        })
+    }
+    ///Unknown counters are left empty.
+    fn render(&self, counters: &[Status]) -> String {
+        let mut out = String::new();
+        for part in &self.0 {
+            match part {
+                Token::Literal(literal) => out.push_str(literal),
+                Token::Field(name) => if let Some(status) = counters.iter().find(|status| status.name.as_ref() == name) {
+                    out.push_str(&status.text);
+                },
+            }
+        }
+        out
+    }
+}
+
+#[derive(Serialize)]
+struct StatusFile<'a> {
+    updated: DateTime<Utc>,
+    counters: &'a [Status],
+}
+
+///One of the files the combined output writes.
+struct File {
+    status: WriteStatus,
+    errors: ErrorThrottle,
+}
+
+impl File {
+    fn new(name: &str) -> Self {
+        Self {
+            status: WriteStatus { output: name.to_string(), last_write: None, error: None },
+            errors: ErrorThrottle::default(),
+        }
+    }
+    async fn write(&mut self, path: &std::path::Path, text: Result<String, String>, popups: &ArcPopupStore) {
+        let result = match text {
+            Ok(text) => replace_file(path, &text).await.map_err(|err| err.to_string()),
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => {
+                self.status.last_write = Some(Utc::now());
+                self.status.error = None;
+            }
+            Err(err) => {
+                self.errors.report(&format!("the {} {}", self.status.output, path.display()), &err, popups);
+                self.status.error = Some(err);
+            }
+        }
+    }
+}
+
+///Waits, until one of the counters changes. Removed counters never change.
+async fn any_changed(counters: &mut [watch::Receiver<Status>]) {
+    if counters.is_empty() {
+        return std::future::pending().await;
+    }
+    let changes = counters.iter_mut().map(|counter| Box::pin(async {
+        if counter.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }));
+    futures_util::future::select_all(changes).await;
+}
+
+///Writes the combined files, whenever a counter changes.
+///Stops when dropped, but never in the middle of a write, so that no temporary file is left behind.
+pub(super) struct Combined {
+    config: Arc<RwLock<CombinedConfig>>,
+    status: watch::Receiver<Vec<WriteStatus>>,
+    _shutdown: watch::Sender<()>,
+}
+
+impl Combined {
+    fn start(config: &CombinedConfig, registry: Registry, popups: ArcPopupStore) -> Self {
+        let config = Arc::new(RwLock::new(config.clone()));
+        let (status_send, status) = watch::channel(Vec::new());
+        let (shutdown_send, mut shutdown) = watch::channel(());
+        let shared = config.clone();
+        tokio::spawn(async move {
+            let mut combined = File::new("combined file");
+            let mut status_file = File::new("status file");
+            loop {
+                let config = shared.read().unwrap_or_else(|err| err.into_inner()).clone();
+                let mut receivers = registry.receivers();
+                let counters: Vec<Status> = receivers.iter_mut().map(|counter| counter.borrow_and_update().clone()).collect();
+                if !config.file.as_os_str().is_empty() {
+                    let text = CombinedTemplate::from_str(&config.template)
+                        .map(|template| template.render(&counters))
+                        .map_err(|err| format!("The template is invalid: {err}"));
+                    combined.write(&config.file, text, &popups).await;
+                }
+                if !config.status_file.as_os_str().is_empty() {
+                    let json = serde_json::to_string_pretty(&StatusFile { updated: Utc::now(), counters: &counters })
+                        .map_err(|err| err.to_string());
+                    status_file.write(&config.status_file, json, &popups).await;
+                }
+                status_send.send_replace(vec![combined.status.clone(), status_file.status.clone()]);
+                tokio::select! {
+                    //Stopping wins over changes, that came in at the same time
+                    biased;
+                    _ = shutdown.changed() => break,
+                    () = any_changed(&mut receivers) => {}
+                    //Picks up added counters and config changes
+                    () = tokio::time::sleep(crate::PERIOD) => {}
+                }
+            }
+        });
+        Self {
+            config,
+            status,
+            _shutdown: shutdown_send,
+        }
+    }
+    fn set_config(&self, config: &CombinedConfig) {
+        let mut lock = self.config.write().unwrap_or_else(|err| err.into_inner());
+        if *lock != *config {
+            lock.clone_from(config);
+        }
+    }
+}
+
+///Which path a file dialog is picking
+#[derive(Copy, Clone)]
+pub(super) enum Pick {
+    File,
+    StatusFile,
+}
+
+impl App {
+    pub(super) fn update_combined(&mut self) {
+        match &self.other_app_state.combined {
+            Some(combined) if self.combined.enabled => combined.set_config(&self.combined),
+            _ => {
+                self.other_app_state.combined = None;
+                if self.combined.enabled {
+                    self.other_app_state.combined = Some(Combined::start(
+                        &self.combined,
+                        self.other_app_state.registry.clone(),
+                        self.other_app_state.popup.clone(),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn check_combined_pick(&mut self) {
+        let Some((pick, task)) = self.other_app_state.combined_pick.take() else {
+            return;
+        };
+        if !task.is_finished() {
+            self.other_app_state.combined_pick = Some((pick, task));
+            return;
+        }
+        match get_runtime().block_on(task) {
+            Ok(Some(file)) => match pick {
+                Pick::File => self.combined.file = file.path().to_path_buf(),
+                Pick::StatusFile => self.combined.status_file = file.path().to_path_buf(),
+            },
+            Ok(None) => log::info!("No File Selected."),
+            Err(err) => {
+                log::error!("Panic whilst picking File: {err}");
+                handle_display_popup_arc(
+                    &self.other_app_state.popup,
+                    "A critical internal app error occurred whilst picking a File",
+                    &err,
+                    "Critical error whilst picking File"
+                );
+            }
+        }
+    }
+
+    fn pick_combined(&mut self, pick: Pick, name: &str) {
+        if let Some((_, task)) = self.other_app_state.combined_pick.take() {
+            task.abort();
+        }
+        let task: JoinHandle<Option<FileHandle>> = tokio::spawn(rfd::AsyncFileDialog::new().set_directory(".").set_file_name(name).save_file());
+        self.other_app_state.combined_pick = Some((pick, task));
+    }
+
+    pub(super) fn combined_window(&mut self, ctx: &egui::Context) {
+        self.check_combined_pick();
+        let mut open = self.other_app_state.combined_open;
+        egui::Window::new("Combined Output")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut self.combined.enabled, "Enabled")
+                    .on_hover_text("Writes both files whenever a Counter changes, replacing them atomically.");
+                ui.horizontal(|ui| {
+                    ui.label("File:");
+                    ui.label(self.combined.file.to_string_lossy());
+                    if ui.button("Select File").clicked() {
+                        self.pick_combined(Pick::File, "counters.txt");
+                    }
+                });
+                ui.label("Template. {name} is replaced with the Counter called name, in its format. Only Counters of the shown Profile are available.");
+                ui.add(egui::TextEdit::multiline(&mut self.combined.template).hint_text("Break: {Break} | Stream: {Stream}"));
+                match CombinedTemplate::from_str(&self.combined.template) {
+                    Ok(template) => {
+                        let registry = self.other_app_state.registry.snapshot();
+                        for name in template.counters() {
+                            if !registry.iter().any(|status| status.name.as_ref() == name) {
+                                ui.colored_label(ui.visuals().warn_fg_color, format!("There is no Counter named '{name}'. It is left empty."));
+                            }
+                        }
+                        ui.label(format!("Preview: {}", template.render(&registry)));
+                    }
+                    Err(err) => {
+                        ui.colored_label(ui.visuals().error_fg_color, err.to_string());
+                    }
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Status File:");
+                    ui.label(self.combined.status_file.to_string_lossy());
+                    if ui.button("Select File").clicked() {
+                        self.pick_combined(Pick::StatusFile, "counters.json");
+                    }
+                }).response.on_hover_text("Json with the name, mode, state, value and text of every Counter in the shown Profile.");
+                if let Some(combined) = &self.other_app_state.combined {
+                    for status in combined.status.borrow().iter() {
+                        if let Some(error) = &status.error {
+                            ui.colored_label(ui.visuals().error_fg_color, format!("Writing the {} failed: {error}", status.output));
+                        } else if let Some(last_write) = status.last_write {
+                            ui.label(format!("{} last written: {}", status.output, last_write.with_timezone(&chrono::Local).format("%H:%M:%S")));
+                        }
+                    }
+                }
+            });
+        self.other_app_state.combined_open = open;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    fn status(name: &str, text: &str) -> Status {
+        Status { name: Arc::from(name), text: text.to_string(), ..Status::default() }
+    }
+
+    async fn wait_for(path: &std::path::Path, expected: &str, within: Duration) -> bool {
+        let until = tokio::time::Instant::now() + within;
+        while tokio::time::Instant::now() < until {
+            if tokio::fs::read_to_string(path).await.is_ok_and(|text| text == expected) {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn follows_counter_changes() {
+        let dir = std::env::temp_dir().join(format!("counter-combined-test-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let file = dir.join("combined.txt");
+        let (sender, receiver) = watch::channel(status("Break", "00:05"));
+        let registry = Registry::default();
+        registry.publish(vec![receiver]);
+        let config = CombinedConfig {
+            enabled: true,
+            file: file.clone(),
+            template: "Break: {Break}".to_string(),
+            status_file: PathBuf::new(),
+        };
+        let combined = Combined::start(&config, registry, ArcPopupStore::default());
+        assert!(wait_for(&file, "Break: 00:05", Duration::from_millis(500)).await);
+        //Much sooner than the fallback of a second
+        sender.send_replace(status("Break", "00:04.9"));
+        assert!(wait_for(&file, "Break: 00:04.9", Duration::from_millis(300)).await);
+
+        drop(combined);
+        sender.send_replace(status("Break", "00:04.8"));
+        assert!(!wait_for(&file, "Break: 00:04.8", Duration::from_millis(300)).await);
+        let mut entries = tokio::fs::read_dir(&dir).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            assert_eq!(entry.file_name(), "combined.txt", "A temporary file was left behind");
+        }
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
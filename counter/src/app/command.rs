@@ -0,0 +1,121 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Operation {
+    Start,
+    Stop,
+    Reset,
+    ///Adds nanoseconds to the current value
+    Add(i64),
+    ///Sets the current value in nanoseconds. For a stopped counter, also the start value.
+    Set(i64),
+    ///Adds nanoseconds, but never pushes the value above `max`
+    AddCapped { nanos: i64, max: i64 },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum CommandError {
+    UnknownCounter(Arc<str>),
+    ///The counter's mode doesn't allow changing it's value while running
+    NotAdjustable(Arc<str>),
+    ///The counter is a [`crate::counter_or_timer::Mode::Tally`], so time can't be added or set
+    NotTimed(Arc<str>),
+    ///Nothing applies commands anymore (e.g. the app is shutting down)
+    NotListening,
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownCounter(name) => write!(f, "There is no Counter named '{name}'"),
+            Self::NotAdjustable(name) => write!(f, "The value of the Counter '{name}' can't be changed while it is running in its mode"),
+            Self::NotTimed(name) => write!(f, "The Counter '{name}' is a Tally, which counts instead of keeping time. Use its buttons to change the count"),
+            Self::NotListening => write!(f, "The app is not accepting commands"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+///A request to change a counter, that doesn't originate from the counter's own ui.
+///It is applied by [`crate::app::App`] on the next frame.
+#[derive(Debug)]
+pub(crate) struct Command {
+    pub counter: Arc<str>,
+    pub operation: Operation,
+    ///Receives the outcome, if someone is waiting for it.
+    ///`Ok` contains the nanoseconds, that were added to the value.
+    pub reply: Option<oneshot::Sender<Result<i64, CommandError>>>,
+}
+
+#[derive(Clone)]
+pub(crate) struct CommandSender {
+    sender: UnboundedSender<Command>,
+    repaint: Option<egui::Context>,
+}
+
+impl CommandSender {
+    fn send_command(&self, command: Command) -> Result<(), CommandError> {
+        if let Err(err) = self.sender.send(command) {
+            log::warn!("Nothing is listening for commands anymore. Dropped command: {:?}", err.0);
+            return Err(CommandError::NotListening);
+        }
+        //Apply the command as soon as possible, even if the ui is idle.
+        if let Some(ctx) = &self.repaint {
+            ctx.request_repaint();
+        }
+        Ok(())
+    }
+    ///Sends a command without waiting for the outcome. Errors are shown as popups.
+    pub fn send(&self, counter: Arc<str>, operation: Operation) {
+        let _ = self.send_command(Command { counter, operation, reply: None });
+    }
+    ///Sends a command and waits for the outcome.
+    ///Returns the nanoseconds, that were added, which for [`Operation::AddCapped`] can be less than asked for.
+    pub async fn request(&self, counter: Arc<str>, operation: Operation) -> Result<i64, CommandError> {
+        let (reply, receiver) = oneshot::channel();
+        self.send_command(Command { counter, operation, reply: Some(reply) })?;
+        receiver.await.unwrap_or(Err(CommandError::NotListening))
+    }
+}
+
+impl Default for CommandSender {
+    ///A sender, that is not connected to any [`CommandChannel`].
+    fn default() -> Self {
+        CommandChannel::default().sender
+    }
+}
+
+pub(crate) struct CommandChannel {
+    sender: CommandSender,
+    receiver: UnboundedReceiver<Command>,
+}
+
+impl Default for CommandChannel {
+    fn default() -> Self {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        Self {
+            sender: CommandSender { sender, repaint: None },
+            receiver,
+        }
+    }
+}
+
+impl CommandChannel {
+    ///Makes every sender cloned afterward wake up the ui.
+    pub fn set_repaint(&mut self, ctx: egui::Context) {
+        self.sender.repaint = Some(ctx);
+    }
+    pub fn sender(&self) -> CommandSender {
+        self.sender.clone()
+    }
+    pub fn try_recv(&mut self) -> Option<Command> {
+        self.receiver.try_recv().ok()
+    }
+    pub async fn recv(&mut self) -> Option<Command> {
+        self.receiver.recv().await
+    }
+}
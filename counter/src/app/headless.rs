@@ -0,0 +1,146 @@
+use std::path::Path;
+use crate::app::App;
+use crate::app::command::Command;
+use crate::counter_or_timer::spec::{CounterSet, SET_VERSION};
+use crate::get_runtime;
+
+enum Wake {
+    Shutdown,
+    Command(Command),
+    Tick,
+}
+
+///Resolves on SIGINT (Ctrl+C) or SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => log::info!("Received SIGINT"),
+                    _ = terminate.recv() => log::info!("Received SIGTERM"),
+                }
+                return;
+            }
+            Err(err) => log::error!("Failed to listen for SIGTERM. Only SIGINT will stop the counters: {err}"),
+        }
+    }
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        log::error!("Failed to listen for Ctrl+C. Stop the process some other way: {err}");
+        std::future::pending::<()>().await;
+    }
+    log::info!("Received Ctrl+C");
+}
+
+impl App {
+    ///Loads the counters from a file, that is either
+    ///- a counter set, as written by "Export…" (see [`crate::counter_or_timer::spec`]), or
+    ///- the saved app state, which also contains the settings of the http server, the chat and the combined output.
+    ///
+    ///Shell commands in the file are run, like the ones of counters in the app.
+    fn from_config(path: &Path) -> Result<Self, ()> {
+        let state = std::fs::read_to_string(path).map_err(|err| {
+            log::error!("Failed to read the config file {}: {err}", path.display());
+        })?;
+        let parsed = match serde_json::from_str::<serde_json::Value>(&state) {
+            //The app state keeps its counters in an object
+            Ok(value) if value.get("counters").is_some_and(serde_json::Value::is_array) => Self::from_counter_set(value),
+            _ => Self::parse_state(&state),
+        };
+        let mut slf = parsed.map_err(|err| {
+            log::error!("Failed to parse the config file {}: {err}", path.display());
+        })?;
+        slf.init_counters();
+        Ok(slf)
+    }
+
+    fn from_counter_set(value: serde_json::Value) -> Result<Self, Box<dyn std::error::Error>> {
+        let set = serde_json::from_value::<CounterSet>(value)?;
+        if set.version > SET_VERSION {
+            return Err(format!("The counter set has version {}, but this app only knows up to version {SET_VERSION}", set.version).into());
+        }
+        set.validate()?;
+        let mut slf = Self::default();
+        slf.apply_counter_set(Some(set), true);
+        Ok(slf)
+    }
+
+    ///Popups can't be shown without a ui. Everything shown in them has already been logged.
+    fn discard_popups(&mut self) {
+        get_runtime().block_on(self.other_app_state.popup.lock()).clear();
+    }
+
+    fn wait(&mut self, shutdown: &mut tokio::task::JoinHandle<()>) -> Wake {
+        let commands = &mut self.other_app_state.commands;
+        get_runtime().block_on(async {
+            tokio::select! {
+                _ = shutdown => Wake::Shutdown,
+                Some(command) = commands.recv() => Wake::Command(command),
+                () = tokio::time::sleep(crate::PERIOD) => Wake::Tick,
+            }
+        })
+    }
+}
+
+///Runs every counter from the config file until SIGINT or SIGTERM is received.
+pub(crate) fn run(config: &Path) -> Result<(), ()> {
+    let mut app = App::from_config(config)?;
+    let mut shutdown = get_runtime().spawn(shutdown_signal());
+    for id in app.order.clone() {
+        if let Some(counter) = app.counters.get_mut(&id) {
+            if !counter.resume_counter() {
+                log::info!("Starting Counter {}", counter.name.as_ref());
+                counter.start_counter();
+            }
+        }
+    }
+    loop {
+        app.process_commands();
+        app.publish();
+        app.update_server();
+        app.update_chat();
+        app.update_combined();
+        app.discard_popups();
+        match app.wait(&mut shutdown) {
+            Wake::Shutdown => break,
+            Wake::Command(command) => app.handle_command(command),
+            Wake::Tick => {}
+        }
+    }
+    log::info!("Stopping all Counters");
+    app.stop_all();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, json: &str) -> Result<App, ()> {
+        let path = std::env::temp_dir().join(format!("counter-headless-{name}-{}.json", std::process::id()));
+        std::fs::write(&path, json).unwrap();
+        let app = App::from_config(&path);
+        std::fs::remove_file(&path).unwrap();
+        app
+    }
+
+    fn names(app: &App) -> Vec<&str> {
+        app.order.iter().map(|id| app.counters[id].name.as_ref()).collect()
+    }
+
+    #[test]
+    fn counter_set_config() {
+        let app = load("set", r#"{"version": 2, "counters": [{"name": "Break", "mode": "Timer", "outputs": []}, {"name": "Deaths", "mode": "Tally"}]}"#).unwrap();
+        assert_eq!(names(&app), ["Break", "Deaths"]);
+        assert!(load("too-new", &format!(r#"{{"version": {}, "counters": []}}"#, SET_VERSION + 1)).is_err());
+        assert!(load("duplicate", r#"{"version": 2, "counters": [{"name": "Break"}, {"name": "Break"}]}"#).is_err());
+    }
+
+    #[test]
+    fn app_state_config() {
+        let app = load("state", r#"{"version": 1, "next_name": "", "next_id": 1, "order": [0], "counters": {"0": {"name": "Deaths", "mode": "Tally", "outputs": []}}}"#).unwrap();
+        assert_eq!(names(&app), ["Deaths"]);
+        assert!(load("invalid", "{").is_err());
+    }
+}
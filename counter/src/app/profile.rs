@@ -0,0 +1,171 @@
+use std::collections::{BTreeMap, HashSet};
+use egui::Context;
+use egui::ahash::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::app::{popup, App};
+use crate::counter_or_timer::{CounterId, CounterTimer};
+
+pub(super) const DEFAULT_PROFILE: &str = "Default";
+
+pub(super) fn default_profile() -> String {
+    DEFAULT_PROFILE.to_string()
+}
+
+pub(super) type Profiles = BTreeMap<String, Profile>;
+
+///The counters of a profile, that is currently not shown.
+#[derive(Default, Deserialize, Serialize)]
+pub(super) struct Profile {
+    #[serde(default)]
+    pub(super) order: Vec<CounterId>,
+    #[serde(default)]
+    pub(super) counters: HashMap<CounterId, CounterTimer>,
+}
+
+///Makes sure, that every counter is shown exactly once.
+pub(super) fn repair_order(order: &mut Vec<CounterId>, counters: &HashMap<CounterId, CounterTimer>) {
+    let mut seen = HashSet::new();
+    order.retain(|id| counters.contains_key(id) && seen.insert(*id));
+    let mut missing: Vec<_> = counters.keys()
+        .filter(|id| !seen.contains(id))
+        .copied()
+        .collect();
+    missing.sort();
+    order.append(&mut missing);
+}
+
+impl App {
+    ///The counters of every profile, including the shown one.
+    pub(super) fn all_counters_mut(&mut self) -> impl Iterator<Item = (&CounterId, &mut CounterTimer)> {
+        self.counters.iter_mut()
+            .chain(self.profiles.values_mut().flat_map(|profile| profile.counters.iter_mut()))
+    }
+
+    pub(super) fn repair_profiles(&mut self) {
+        repair_order(&mut self.order, &self.counters);
+        for profile in self.profiles.values_mut() {
+            repair_order(&mut profile.order, &profile.counters);
+        }
+        //The shown profile is never in the map
+        if self.profiles.remove(&self.profile).is_some() {
+            log::warn!("Dropped a second Profile named {}", self.profile);
+        }
+    }
+
+    ///Shows the counters of another profile.
+    ///Running counters of the current profile either get stopped, or keep running without being shown.
+    ///Counters, that keep running, still write their outputs and run their actions.
+    ///Only the shown profile is published, so the http server, the chat and commands of actions can't reach them.
+    fn switch_profile(&mut self, name: &str, stop_running: bool) {
+        let Some(next) = self.profiles.remove(name) else {
+            return;
+        };
+        if stop_running {
+            for counter in self.counters.values_mut() {
+                counter.stop_counter();
+            }
+        }
+        log::info!("Switching from Profile {} to {name}", self.profile);
+        let current = Profile {
+            order: core::mem::replace(&mut self.order, next.order),
+            counters: core::mem::replace(&mut self.counters, next.counters),
+        };
+        let current_name = core::mem::replace(&mut self.profile, name.to_string());
+        self.profiles.insert(current_name, current);
+        self.other_app_state.renaming.clear();
+    }
+
+    ///Asks what to do with running counters, if there are any.
+    fn request_switch_profile(&mut self, name: String) {
+        let running = self.counters.values_mut().map(|counter| counter.is_running()).filter(|running| *running).count();
+        if running == 0 {
+            self.switch_profile(&name, false);
+            return;
+        }
+        popup::popup_creator(self.other_app_state.popup.clone(), "Switch Profile", move |app, ui, _, close| {
+            ui.label(format!("{running} Counters are running in Profile {}.", app.profile));
+            ui.horizontal(|ui| {
+                if ui.button("Stop them").clicked() {
+                    app.switch_profile(&name, true);
+                    *close = true;
+                }
+                if ui.button("Keep them running").on_hover_text("They keep writing their files, but are only shown again, once you switch back. Until then the http server and chat can't control them.").clicked() {
+                    app.switch_profile(&name, false);
+                    *close = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    *close = true;
+                }
+            });
+        });
+    }
+
+    pub(super) fn profile_switcher(&mut self, ui: &mut egui::Ui) {
+        let mut selected = None;
+        egui::ComboBox::from_id_source("profile")
+            .selected_text(format!("Profile: {}", self.profile))
+            .show_ui(ui, |ui| {
+                let mut names: Vec<&String> = self.profiles.keys().collect();
+                names.push(&self.profile);
+                names.sort();
+                for name in names {
+                    if ui.selectable_label(*name == self.profile, name.as_str()).clicked() && *name != self.profile {
+                        selected = Some(name.clone());
+                    }
+                }
+            });
+        if let Some(name) = selected {
+            self.request_switch_profile(name);
+        }
+        if ui.button("Profiles").clicked() {
+            self.other_app_state.profiles_open = !self.other_app_state.profiles_open;
+        }
+    }
+
+    pub(super) fn profiles_window(&mut self, ctx: &Context) {
+        let mut open = self.other_app_state.profiles_open;
+        egui::Window::new("Profiles")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label("Each Profile has its own Counters. Switch between them in the top bar.");
+                ui.label(format!("{} (shown)", self.profile));
+                let mut remove = None;
+                for (name, profile) in &mut self.profiles {
+                    ui.horizontal(|ui| {
+                        ui.label(name.as_str());
+                        let running = profile.counters.values_mut().map(|counter| counter.is_running()).filter(|running| *running).count();
+                        if running > 0 {
+                            ui.label(format!("({running} running)"));
+                        }
+                        if ui.button("Delete").clicked() {
+                            remove = Some(name.clone());
+                        }
+                    });
+                }
+                if let Some(name) = remove {
+                    if let Some(mut profile) = self.profiles.remove(&name) {
+                        for counter in profile.counters.values_mut() {
+                            counter.stop_counter();
+                        }
+                    }
+                }
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.other_app_state.next_profile);
+                    if ui.button("Add Profile").clicked() {
+                        let name = self.other_app_state.next_profile.trim().to_string();
+                        if name.is_empty() || name == self.profile || self.profiles.contains_key(&name) {
+                            self.other_app_state.profile_err = true;
+                        } else {
+                            self.other_app_state.profile_err = false;
+                            self.other_app_state.next_profile.clear();
+                            self.profiles.insert(name, Profile::default());
+                        }
+                    }
+                });
+                if self.other_app_state.profile_err {
+                    ui.colored_label(ui.visuals().error_fg_color, "Please provide a unique, non-empty name.");
+                }
+            });
+        self.other_app_state.profiles_open = open;
+    }
+}
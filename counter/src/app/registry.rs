@@ -0,0 +1,35 @@
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+use crate::counter_or_timer::Status;
+
+///A read-only view of all counters for everything outside the ui thread.
+///[`crate::app::App`] republishes the counters every frame.
+#[derive(Clone, Default)]
+pub(crate) struct Registry(Arc<RwLock<Vec<watch::Receiver<Status>>>>);
+
+impl Registry {
+    pub fn publish(&self, counters: Vec<watch::Receiver<Status>>) {
+        match self.0.write() {
+            Ok(mut lock) => *lock = counters,
+            Err(err) => {
+                log::error!("The counter registry was poisoned. Recovering: {err}");
+                *err.into_inner() = counters;
+                self.0.clear_poison();
+            }
+        }
+    }
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Vec<watch::Receiver<Status>>> {
+        self.0.read().unwrap_or_else(|err| err.into_inner())
+    }
+    ///The current status of every counter, in display order.
+    pub fn snapshot(&self) -> Vec<Status> {
+        self.read().iter().map(|counter| counter.borrow().clone()).collect()
+    }
+    ///Receivers of every counter, in display order, to follow their changes.
+    pub fn receivers(&self) -> Vec<watch::Receiver<Status>> {
+        self.read().clone()
+    }
+    pub fn find(&self, name: &str) -> Option<watch::Receiver<Status>> {
+        self.read().iter().find(|counter| counter.borrow().name.as_ref() == name).cloned()
+    }
+}
@@ -0,0 +1,186 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use egui::ahash::HashMap;
+use crate::app::profile::Profile;
+use crate::app::{popup, App};
+use crate::counter_or_timer::{CounterId, CounterTimer};
+
+mod migration;
+
+impl App {
+    pub(super) fn to_state(&self) -> serde_json::Result<String> {
+        let mut state = serde_json::to_value(self)?;
+        migration::set_version(&mut state);
+        serde_json::to_string(&state)
+    }
+
+    ///Parses the saved state, upgrading it from older versions if needed.
+    pub(super) fn parse_state(state: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut state = serde_json::from_str(state)?;
+        migration::migrate(&mut state)?;
+        Ok(serde_json::from_value(state)?)
+    }
+
+    ///Restores the app from the saved state.
+    ///Unreadable state is backed up, and as much of it as possible is recovered.
+    pub(super) fn from_state(state: &str) -> Self {
+        let err = match Self::parse_state(state) {
+            Ok(slf) => return slf,
+            Err(err) => err,
+        };
+        log::error!("Failed deserialising App State. Will try to recover as much as possible. Error: {err}");
+        let backup = backup(state);
+        let mut value = serde_json::from_str::<Value>(state).ok();
+        //Older fields would be lost without migrating
+        if let Some(Err(err)) = value.as_mut().map(migration::migrate) {
+            log::warn!("Failed migrating the App State. Recovering it as is. Error: {err}");
+        }
+        let (slf, lost) = match value {
+            Some(Value::Object(fields)) => Self::recover(fields),
+            _ => (Self::default(), vec!["Everything".to_string()]),
+        };
+        let mut label = match &backup {
+            Ok(path) => format!("The App State could not be loaded completely. A backup of it was written to {}.", path.display()),
+            Err(backup_err) => {
+                log::error!("Failed to back up the App State: {backup_err}");
+                format!("The App State could not be loaded completely. Backing it up failed as well: {backup_err}")
+            }
+        };
+        if !lost.is_empty() {
+            label.push_str("\nReset to the defaults: ");
+            label.push_str(&lost.join(", "));
+        }
+        popup::handle_display_popup_arc(
+            &slf.other_app_state.popup,
+            label,
+            &err,
+            "Failed to Load App State."
+        );
+        slf
+    }
+
+    ///Deserializes every field and every counter on it's own.
+    ///Returns the recovered app and a description of what couldn't be recovered.
+    fn recover(mut fields: Map<String, Value>) -> (Self, Vec<String>) {
+        let mut slf = Self::default();
+        let mut lost = Vec::new();
+        fields.remove("version");
+        recover_field(&mut fields, "next_name", &mut slf.next_name, &mut lost);
+        recover_field(&mut fields, "server", &mut slf.server, &mut lost);
+        recover_field(&mut fields, "chat", &mut slf.chat, &mut lost);
+        recover_field(&mut fields, "combined", &mut slf.combined, &mut lost);
+        recover_field(&mut fields, "resume_on_launch", &mut slf.resume_on_launch, &mut lost);
+        recover_field(&mut fields, "profile", &mut slf.profile, &mut lost);
+        match fields.remove("profiles") {
+            Some(Value::Object(profiles)) => {
+                for (name, profile) in profiles {
+                    let Value::Object(mut profile) = profile else {
+                        log::warn!("Failed recovering Profile {name}: it is not an object");
+                        lost.push(format!("Profile '{name}'"));
+                        continue;
+                    };
+                    //Missing or unknown ids are fixed by App::init_counters
+                    let order = profile.remove("order")
+                        .and_then(|order| serde_json::from_value(order).ok())
+                        .unwrap_or_default();
+                    let counters = recover_counters(profile.remove("counters"), &format!(" of Profile '{name}'"), &mut lost);
+                    slf.profiles.insert(name, Profile { order, counters });
+                }
+            }
+            None => {}
+            Some(_) => lost.push("all Profiles".to_string()),
+        }
+        recover_field(&mut fields, "next_id", &mut slf.next_id, &mut lost);
+        //Missing or unknown ids are fixed by App::init_counters
+        recover_field(&mut fields, "order", &mut slf.order, &mut lost);
+        slf.counters = recover_counters(fields.remove("counters"), "", &mut lost);
+        (slf, lost)
+    }
+}
+
+///Deserializes every counter on it's own. `of` describes where the counters are from in `lost`.
+fn recover_counters(counters: Option<Value>, of: &str, lost: &mut Vec<String>) -> HashMap<CounterId, CounterTimer> {
+    let mut recovered = HashMap::default();
+    match counters {
+        Some(Value::Object(counters)) => {
+            for (id, counter) in counters {
+                let name = counter.get("name").and_then(Value::as_str).unwrap_or(&id).to_string();
+                match u64::from_str(&id).map_err(|err| err.to_string())
+                    .and_then(|id| Ok((id, serde_json::from_value::<CounterTimer>(counter).map_err(|err| err.to_string())?)))
+                {
+                    Ok((id, counter)) => {
+                        recovered.insert(CounterId(id), counter);
+                    }
+                    Err(err) => {
+                        log::warn!("Failed recovering Counter {name}{of}: {err}");
+                        lost.push(format!("Counter '{name}'{of}"));
+                    }
+                }
+            }
+        }
+        None => {}
+        Some(_) => lost.push(format!("all Counters{of}")),
+    }
+    recovered
+}
+
+fn recover_field<T: DeserializeOwned>(fields: &mut Map<String, Value>, name: &str, out: &mut T, lost: &mut Vec<String>) {
+    let Some(value) = fields.remove(name) else {
+        return;
+    };
+    match serde_json::from_value(value) {
+        Ok(value) => *out = value,
+        Err(err) => {
+            log::warn!("Failed recovering {name}: {err}");
+            lost.push(format!("'{name}'"));
+        }
+    }
+}
+
+///Writes the state next to the app's storage, so that the next autosave doesn't destroy it.
+fn backup(state: &str) -> std::io::Result<PathBuf> {
+    let dir = eframe::storage_dir(crate::APP_NAME).unwrap_or_default();
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("app-state-backup-{}.json", chrono::Local::now().format("%Y-%m-%d_%H-%M-%S")));
+    std::fs::write(&path, state)?;
+    log::info!("Backed up the App State to {}", path.display());
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn recover_profiles_per_counter() {
+        let state = json!({
+            "next_name": "",
+            "order": [0, 1],
+            "counters": {
+                "0": {"name": "Stream", "mode": "Counter"},
+                "1": {"name": "Broken", "mode": "NoSuchMode"},
+            },
+            "profiles": {
+                "Weekend": {"order": [0, 1], "counters": {
+                    "0": {"name": "Marathon", "mode": "Timer"},
+                    "1": {"name": "Bad", "mode": 5},
+                }},
+                "Empty": {},
+                "Garbage": 7,
+            },
+        });
+        let Value::Object(fields) = state else { unreachable!() };
+        let (app, mut lost) = App::recover(fields);
+        lost.sort();
+        assert_eq!(lost, ["Counter 'Bad' of Profile 'Weekend'", "Counter 'Broken'", "Profile 'Garbage'"]);
+        assert_eq!(app.counters.len(), 1);
+        let weekend = &app.profiles["Weekend"];
+        assert_eq!(weekend.counters.len(), 1);
+        assert_eq!(weekend.counters[&CounterId(0)].name.as_ref(), "Marathon");
+        assert!(app.profiles["Empty"].counters.is_empty());
+        assert!(!app.profiles.contains_key("Garbage"));
+    }
+}
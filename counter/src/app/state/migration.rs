@@ -0,0 +1,247 @@
+use std::fmt::{Display, Formatter};
+use serde_json::{Map, Value};
+
+const VERSION_KEY: &str = "version";
+const NANOS_IN_SECOND: i64 = 1_000_000_000;
+
+type Migration = fn(&mut Map<String, Value>);
+
+///Each entry upgrades the state from the version equal to it's index to the next version.
+///States without a version are from v0.2.2 or earlier, and are version 0.
+///Only add an entry for a layout that a release has written.
+const MIGRATIONS: &[Migration] = &[
+    v0_2_2,
+];
+
+///The version of the state this app saves.
+pub const CURRENT_VERSION: u64 = MIGRATIONS.len() as u64;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MigrationError {
+    NotAnObject,
+    InvalidVersion(Value),
+    ///Saved by a newer version of the app
+    TooNew(u64),
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotAnObject => write!(f, "The App State is not a json object"),
+            Self::InvalidVersion(version) => write!(f, "The App State has an invalid version: {version}"),
+            Self::TooNew(version) => write!(f, "The App State has version {version}, but this app only knows up to version {CURRENT_VERSION}. Was it saved by a newer version of the app?"),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+///Upgrades the state to [`CURRENT_VERSION`], one version at a time.
+pub fn migrate(state: &mut Value) -> Result<(), MigrationError> {
+    let Value::Object(fields) = state else {
+        return Err(MigrationError::NotAnObject);
+    };
+    let version = match fields.get(VERSION_KEY) {
+        None => 0,
+        Some(version) => version.as_u64().ok_or_else(|| MigrationError::InvalidVersion(version.clone()))?,
+    };
+    if version > CURRENT_VERSION {
+        return Err(MigrationError::TooNew(version));
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        log::info!("Migrating the App State from version {from} to {}", from + 1);
+        migration(fields);
+    }
+    set_version(state);
+    Ok(())
+}
+
+///Marks the state as being in the [`CURRENT_VERSION`].
+pub fn set_version(state: &mut Value) {
+    if let Value::Object(fields) = state {
+        fields.insert(VERSION_KEY.to_string(), Value::from(CURRENT_VERSION));
+    }
+}
+
+fn counters(fields: &mut Map<String, Value>) -> impl Iterator<Item = &mut Map<String, Value>> {
+    fields.get_mut("counters")
+        .and_then(Value::as_object_mut)
+        .into_iter()
+        .flat_map(|counters| counters.values_mut())
+        .filter_map(Value::as_object_mut)
+}
+
+///Upgrades the layout of v0.2.2, the last release before the state had a version.
+fn v0_2_2(fields: &mut Map<String, Value>) {
+    seconds_to_nanos(fields);
+    counter_ids(fields);
+    file_outputs(fields);
+}
+
+///v0.2.2 stored whole seconds in `time_s`, which were both the start and the live value.
+///Now the live value is `time_ns` and the start value is `initial_ns`, both in nanoseconds.
+fn seconds_to_nanos(fields: &mut Map<String, Value>) {
+    for counter in counters(fields) {
+        let Some(time_s) = counter.remove("time_s") else {
+            continue;
+        };
+        let Some(time_s) = time_s.as_i64() else {
+            log::warn!("Dropping invalid time_s {time_s}");
+            continue;
+        };
+        let nanos = Value::from(time_s.saturating_mul(NANOS_IN_SECOND));
+        counter.insert("time_ns".to_string(), nanos.clone());
+        counter.insert("initial_ns".to_string(), nanos);
+    }
+}
+
+///Counters were keyed by their name and shown in the order of `names`.
+///Now they are keyed by a [`crate::counter_or_timer::CounterId`] and shown in the order of `order`.
+fn counter_ids(fields: &mut Map<String, Value>) {
+    let mut names: Vec<String> = match fields.remove("names") {
+        Some(Value::Array(names)) => names.into_iter()
+            .filter_map(|name| name.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let mut seen = std::collections::HashSet::new();
+    names.retain(|name| seen.insert(name.clone()));
+    let mut by_name = match fields.remove("counters") {
+        Some(Value::Object(counters)) => counters,
+        _ => Map::new(),
+    };
+    let mut rest: Vec<String> = by_name.keys()
+        .filter(|name| !names.contains(name))
+        .cloned()
+        .collect();
+    rest.sort();
+    let mut counters = Map::new();
+    let mut order = Vec::new();
+    for name in names.into_iter().chain(rest) {
+        //v0.2.2 showed a new counter for a name without one
+        let mut counter = by_name.remove(&name).unwrap_or_else(|| {
+            let mut counter = Map::new();
+            counter.insert("mode".to_string(), Value::from("Counter"));
+            counter.insert("file".to_string(), Value::from(""));
+            Value::Object(counter)
+        });
+        if let Value::Object(counter) = &mut counter {
+            counter.insert("name".to_string(), Value::from(name));
+        }
+        let id = order.len() as u64;
+        counters.insert(id.to_string(), counter);
+        order.push(Value::from(id));
+    }
+    fields.insert("next_id".to_string(), Value::from(order.len() as u64));
+    fields.insert("order".to_string(), Value::Array(order));
+    fields.insert("counters".to_string(), Value::Object(counters));
+}
+
+///v0.2.2 wrote every counter to one `file`, if one was picked.
+///Now every counter has a list of `outputs`.
+fn file_outputs(fields: &mut Map<String, Value>) {
+    for counter in counters(fields) {
+        let mut outputs = Vec::new();
+        match counter.remove("file") {
+            Some(Value::String(path)) if !path.is_empty() => {
+                let mut output = Map::new();
+                output.insert("kind".to_string(), Value::from("File"));
+                output.insert("path".to_string(), Value::from(path));
+                outputs.push(Value::Object(output));
+            }
+            _ => {}
+        }
+        counter.insert("outputs".to_string(), Value::Array(outputs));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::app::App;
+    use crate::counter_or_timer::CounterId;
+    use super::*;
+
+    const V0_2_2: &str = include_str!("fixtures/v0.2.2.json");
+
+    fn fields(state: &str) -> Map<String, Value> {
+        match serde_json::from_str(state).unwrap() {
+            Value::Object(fields) => fields,
+            other => panic!("not an object: {other}"),
+        }
+    }
+
+    #[test]
+    fn steps_of_v0_2_2() {
+        let mut fields = fields(V0_2_2);
+
+        seconds_to_nanos(&mut fields);
+        assert_eq!(fields["counters"]["Stream"], json!({
+            "name": "Stream", "mode": "Counter", "file": "/home/streamer/stream.txt",
+            "time_ns": 3_725_000_000_000i64, "initial_ns": 3_725_000_000_000i64,
+        }));
+
+        counter_ids(&mut fields);
+        assert_eq!(fields.get("names"), None);
+        //Duplicate names are dropped, names without a counter get one, and counters without a name come last.
+        assert_eq!(fields["order"], json!([0, 1, 2, 3]));
+        assert_eq!(fields["next_id"], json!(4));
+        let names = (0..4).map(|id| fields["counters"][id.to_string()]["name"].clone()).collect::<Vec<_>>();
+        assert_eq!(names, ["Stream", "Break", "Gone", "Clock"]);
+        assert_eq!(fields["counters"]["2"], json!({"name": "Gone", "mode": "Counter", "file": ""}));
+
+        file_outputs(&mut fields);
+        assert_eq!(fields["counters"]["0"], json!({
+            "name": "Stream", "mode": "Counter",
+            "time_ns": 3_725_000_000_000i64, "initial_ns": 3_725_000_000_000i64,
+            "outputs": [{"kind": "File", "path": "/home/streamer/stream.txt"}],
+        }));
+        assert_eq!(fields["counters"]["1"]["outputs"], json!([]));
+        assert_eq!(fields["counters"]["2"]["outputs"], json!([]));
+        assert_eq!(fields["counters"]["3"]["outputs"], json!([{"kind": "File", "path": "/home/streamer/clock.txt"}]));
+    }
+
+    #[test]
+    fn migrate_v0_2_2() {
+        let mut state = serde_json::from_str(V0_2_2).unwrap();
+        migrate(&mut state).unwrap();
+        let mut expected = Value::Object(fields(V0_2_2));
+        for migration in MIGRATIONS {
+            let Value::Object(fields) = &mut expected else { unreachable!() };
+            migration(fields);
+        }
+        expected[VERSION_KEY] = json!(CURRENT_VERSION);
+        assert_eq!(state, expected);
+
+        let app = App::parse_state(V0_2_2).unwrap();
+        assert_eq!(app.order, [CounterId(0), CounterId(1), CounterId(2), CounterId(3)]);
+        assert_eq!(app.counters[&CounterId(0)].name.as_ref(), "Stream");
+        assert_eq!(app.counters[&CounterId(3)].name.as_ref(), "Clock");
+    }
+
+    #[test]
+    fn current_is_unchanged() {
+        let mut state = json!({"version": CURRENT_VERSION, "counters": {"0": {"name": "a", "file": "kept"}}});
+        let expected = state.clone();
+        migrate(&mut state).unwrap();
+        assert_eq!(state, expected);
+    }
+
+    #[test]
+    fn too_new() {
+        let mut state = json!({"version": CURRENT_VERSION + 1, "names": ["a"]});
+        let expected = state.clone();
+        assert_eq!(migrate(&mut state), Err(MigrationError::TooNew(CURRENT_VERSION + 1)));
+        assert_eq!(state, expected);
+        assert!(App::parse_state(&state.to_string()).is_err());
+    }
+
+    #[test]
+    fn invalid_version() {
+        for version in [json!("1"), json!(-1), json!(1.5), json!(null)] {
+            let mut state = json!({"version": version.clone()});
+            assert_eq!(migrate(&mut state), Err(MigrationError::InvalidVersion(version)));
+        }
+        assert_eq!(migrate(&mut json!([])), Err(MigrationError::NotAnObject));
+    }
+}
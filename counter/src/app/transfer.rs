@@ -0,0 +1,138 @@
+use std::sync::Arc;
+use crate::app::{popup, App};
+use crate::counter_or_timer::spec::{CounterSet, SET_VERSION};
+
+impl App {
+    ///Asks for a file and writes every counter to it. See [`crate::counter_or_timer::spec`] for the format.
+    pub(super) fn export_counters(&self) {
+        let set = CounterSet {
+            version: SET_VERSION,
+            counters: self.order.iter()
+                .filter_map(|id| self.counters.get(id))
+                .map(|counter| counter.to_spec())
+                .collect(),
+        };
+        let popups = self.other_app_state.popup.clone();
+        tokio::spawn(async move {
+            let Some(file) = rfd::AsyncFileDialog::new()
+                .set_directory(".")
+                .set_file_name("counters.json")
+                .add_filter("Counter Set", &["json"])
+                .save_file()
+                .await
+            else {
+                log::info!("No File Selected for the export.");
+                return;
+            };
+            let result = match serde_json::to_string_pretty(&set) {
+                Ok(json) => tokio::fs::write(file.path(), json).await.map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            };
+            match result {
+                Ok(()) => log::info!("Exported {} Counters to {}", set.counters.len(), file.path().display()),
+                Err(err) => {
+                    log::error!("Failed exporting Counters: {err}");
+                    popup::handle_display_popup_arc(&popups, "The Counters could not be exported", &err, "Export failed");
+                }
+            }
+        });
+    }
+
+    ///Asks for a file and whether the counters in it should be merged with or replace the current ones.
+    pub(super) fn import_counters(&self) {
+        let popups = self.other_app_state.popup.clone();
+        tokio::spawn(async move {
+            let Some(file) = rfd::AsyncFileDialog::new()
+                .set_directory(".")
+                .add_filter("Counter Set", &["json"])
+                .pick_file()
+                .await
+            else {
+                log::info!("No File Selected for the import.");
+                return;
+            };
+            let set = match tokio::fs::read_to_string(file.path()).await {
+                Ok(json) => serde_json::from_str::<CounterSet>(&json).map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            };
+            let set = set.and_then(|set| {
+                if set.version > SET_VERSION {
+                    return Err(format!("The file has version {}, but this app only knows up to version {SET_VERSION}", set.version));
+                }
+                set.validate()?;
+                Ok(set)
+            });
+            let set = match set {
+                Ok(set) => set,
+                Err(err) => {
+                    log::error!("Failed importing Counters: {err}");
+                    popup::handle_display_popup_arc(&popups, "The Counters could not be imported", &err, "Import failed");
+                    return;
+                }
+            };
+            let mut set = Some(set);
+            //Shell commands from a file are only kept, if the user allows them after seeing them
+            let mut allow_commands = false;
+            popup::popup_creator(popups, "Import Counters", move |app, ui, _, close| {
+                let Some(import) = set.as_ref() else {
+                    *close = true;
+                    return;
+                };
+                ui.label(format!("Found {} Counters.", import.counters.len()));
+                let commands = import.commands().collect::<Vec<_>>();
+                if !commands.is_empty() {
+                    ui.colored_label(ui.visuals().warn_fg_color, "These Counters run shell commands:");
+                    egui::ScrollArea::vertical().max_height(150.).show(ui, |ui| {
+                        for (counter, command) in commands {
+                            ui.label(format!("{counter}: {command}"));
+                        }
+                    });
+                    ui.checkbox(&mut allow_commands, "Import the shell commands. Only allow this, if you trust the file.");
+                }
+                ui.label("Merge updates Counters with the same name and adds the others.");
+                ui.label("Replace stops and removes all current Counters first.");
+                let mut apply = None;
+                ui.horizontal(|ui| {
+                    if ui.button("Merge").clicked() {
+                        apply = Some(false);
+                    }
+                    if ui.button("Replace").clicked() {
+                        apply = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        set = None;
+                    }
+                });
+                if let Some(replace) = apply {
+                    let mut import = set.take();
+                    if !allow_commands {
+                        import.iter_mut().for_each(CounterSet::strip_commands);
+                    }
+                    app.apply_counter_set(import, replace);
+                }
+                *close = set.is_none();
+            });
+        });
+    }
+
+    pub(super) fn apply_counter_set(&mut self, set: Option<CounterSet>, replace: bool) {
+        let Some(set) = set else {
+            return;
+        };
+        if replace {
+            self.stop_shown();
+            self.counters.clear();
+            self.order.clear();
+            self.other_app_state.renaming.clear();
+        }
+        for spec in set.counters {
+            let id = match self.counter_by_name(&spec.name) {
+                Some(counter) => counter.id,
+                None => self.add_counter(Arc::from(spec.name.as_str())),
+            };
+            if let Some(counter) = self.counters.get_mut(&id) {
+                counter.apply_spec(spec);
+            }
+        }
+    }
+}
@@ -0,0 +1,535 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use crate::app::command::{CommandSender, Operation};
+use crate::app::popup::{handle_display_popup_arc, ArcPopupStore};
+use crate::counter_or_timer::{parse_seconds, seconds_to_nanos};
+
+mod message;
+
+use message::Message;
+
+const DEFAULT_HOST: &str = "irc.chat.twitch.tv";
+const DEFAULT_PORT: u16 = 6667;
+///Waiting time before reconnecting after the connection was lost
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+///Twitch asks clients to not send more than 20 messages per 30 seconds
+const REPLY_DELAY: Duration = Duration::from_millis(1500);
+///Replies beyond this are dropped, so that a burst of failing commands doesn't keep answering for minutes
+const MAX_QUEUED_REPLIES: usize = 5;
+
+///Controls counters from chat messages like `!timer add 5m`.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(default)]
+pub(crate) struct ChatConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub nick: String,
+    ///For Twitch an oauth token like `oauth:…`. Empty means no PASS is sent.
+    pub password: String,
+    ///Without the leading `#`
+    pub channel: String,
+    pub commands: Vec<ChatCommand>,
+    ///Comma separated names, that may use the commands
+    pub allowed_users: String,
+    ///Let the channel owner and Twitch moderators use the commands
+    pub allow_moderators: bool,
+    ///Answer errors in chat
+    pub reply: bool,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: DEFAULT_HOST.to_string(),
+            port: DEFAULT_PORT,
+            nick: String::new(),
+            password: String::new(),
+            channel: String::new(),
+            commands: vec![ChatCommand::default()],
+            allowed_users: String::new(),
+            allow_moderators: true,
+            reply: true,
+        }
+    }
+}
+
+///A chat command prefix and the counter it controls.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(default)]
+pub(crate) struct ChatCommand {
+    pub prefix: String,
+    pub counter: String,
+}
+
+impl Default for ChatCommand {
+    fn default() -> Self {
+        Self {
+            prefix: "!timer".to_string(),
+            counter: String::new(),
+        }
+    }
+}
+
+impl ChatConfig {
+    ///Everything, that needs a new connection, when it changes.
+    fn connection(&self) -> (&str, u16, &str, &str, &str) {
+        (&self.host, self.port, &self.nick, &self.password, &self.channel)
+    }
+    fn channel(&self) -> String {
+        format!("#{}", self.channel.trim_start_matches('#').to_lowercase())
+    }
+    fn is_allowed(&self, message: &Message) -> bool {
+        let Some(user) = message.nick() else {
+            return false;
+        };
+        if self.allowed_users.split(',').any(|allowed| allowed.trim().eq_ignore_ascii_case(user)) {
+            return true;
+        }
+        self.allow_moderators && (
+            user.eq_ignore_ascii_case(self.channel.trim_start_matches('#'))
+            || message.tag("mod") == Some("1")
+            || message.tag("badges").is_some_and(|badges| badges.split(',').any(|badge| badge.starts_with("broadcaster/") || badge.starts_with("moderator/")))
+        )
+    }
+    ///Finds the counter and operation for a chat message.
+    ///Returns `Err` with a usage hint, if the prefix matched, but the rest didn't.
+    fn parse_command(&self, text: &str) -> Option<Result<(&str, Operation), String>> {
+        let mut words = text.split_whitespace();
+        let first = words.next()?;
+        let command = self.commands.iter().find(|command| !command.prefix.is_empty() && command.prefix.eq_ignore_ascii_case(first))?;
+        let operation = words.next().unwrap_or_default().to_lowercase();
+        let time = words.next().and_then(parse_duration).map(seconds_to_nanos);
+        let operation = match (operation.as_str(), time) {
+            ("start" | "resume", _) => Operation::Start,
+            ("stop" | "pause", _) => Operation::Stop,
+            ("reset", _) => Operation::Reset,
+            ("add", Some(time)) => Operation::Add(time),
+            ("sub" | "remove", Some(time)) => Operation::Add(-time),
+            ("set", Some(time)) => Operation::Set(time),
+            _ => return Some(Err(format!("Usage: {} start|stop|reset|add <time>|sub <time>|set <time>", command.prefix))),
+        };
+        Some(Ok((command.counter.as_str(), operation)))
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, chat: Option<&Chat>) {
+        ui.checkbox(&mut self.enabled, "Enabled");
+        match chat {
+            Some(chat) => {
+                ui.label(chat.status());
+            }
+            None => {
+                ui.label("Not connected.");
+            }
+        }
+        egui::Grid::new("chat connection").num_columns(2).show(ui, |ui|{
+            ui.label("Server:");
+            ui.horizontal(|ui|{
+                ui.text_edit_singleline(&mut self.host);
+                ui.add(egui::DragValue::new(&mut self.port));
+            });
+            ui.end_row();
+            ui.label("Nick:");
+            ui.text_edit_singleline(&mut self.nick);
+            ui.end_row();
+            ui.label("Password:");
+            ui.add(egui::TextEdit::singleline(&mut self.password).password(true).hint_text("oauth:…"));
+            ui.end_row();
+            ui.label("Channel:");
+            ui.text_edit_singleline(&mut self.channel);
+            ui.end_row();
+        });
+        ui.label("Commands:");
+        let mut delete = None;
+        for (i, command) in self.commands.iter_mut().enumerate() {
+            ui.horizontal(|ui|{
+                ui.add(egui::TextEdit::singleline(&mut command.prefix).hint_text("!timer").desired_width(80.));
+                ui.label("controls");
+                ui.add(egui::TextEdit::singleline(&mut command.counter).hint_text("Counter name").desired_width(120.));
+                if ui.button("Delete").clicked() {
+                    delete = Some(i);
+                }
+            });
+        }
+        if let Some(i) = delete {
+            self.commands.remove(i);
+        }
+        if ui.button("Add Command").clicked() {
+            self.commands.push(ChatCommand::default());
+        }
+        ui.label("e.g. !timer start, stop, reset, add 5m, sub 1h30m or set 10:00");
+        ui.horizontal(|ui|{
+            ui.label("Allowed users:");
+            ui.add(egui::TextEdit::singleline(&mut self.allowed_users).hint_text("comma separated"));
+        });
+        ui.checkbox(&mut self.allow_moderators, "Allow the channel owner and moderators");
+        ui.checkbox(&mut self.reply, "Answer errors in chat");
+    }
+}
+
+///Parses `5m`, `1h30m`, `90s`, `90` or `1:30` into seconds.
+///Returns `None` for anything else, e.g. `5m30` or `abc`.
+fn parse_duration(text: &str) -> Option<f64> {
+    if !text.ends_with(['h', 'm', 's']) {
+        return parse_seconds(text);
+    }
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let mut seconds = 0.;
+    let mut number = String::new();
+    for char in text.chars() {
+        let unit = match char {
+            'h' => 3600.,
+            'm' => 60.,
+            's' => 1.,
+            '0'..='9' | '.' => {
+                number.push(char);
+                continue;
+            }
+            _ => return None,
+        };
+        if number.is_empty() {
+            return None;
+        }
+        seconds += number.parse::<f64>().ok()? * unit;
+        number.clear();
+    }
+    Some(if negative { -seconds } else { seconds })
+}
+
+#[derive(Clone)]
+struct ChatState {
+    commands: CommandSender,
+    config: Arc<RwLock<ChatConfig>>,
+    status: Arc<watch::Sender<String>>,
+}
+
+impl ChatState {
+    fn config(&self) -> ChatConfig {
+        self.config.read().unwrap_or_else(|err| err.into_inner()).clone()
+    }
+}
+
+///A running irc client. Disconnects when dropped.
+pub(crate) struct Chat {
+    config: Arc<RwLock<ChatConfig>>,
+    status: watch::Receiver<String>,
+    handle: JoinHandle<()>,
+}
+
+impl Chat {
+    ///Connects and reconnects, until dropped. Errors are shown as popups once, until the connection works again.
+    pub fn start(config: &ChatConfig, commands: CommandSender, popups: ArcPopupStore) -> Self {
+        let config = Arc::new(RwLock::new(config.clone()));
+        let (status, status_recv) = watch::channel("Connecting…".to_string());
+        let state = ChatState {
+            commands,
+            config: config.clone(),
+            status: Arc::new(status),
+        };
+        let handle = tokio::spawn(async move {
+            let mut reported = false;
+            loop {
+                let result = run(&state).await;
+                let config = state.config();
+                let addr = format!("{}:{}", config.host, config.port);
+                let err = match result {
+                    Ok(()) => {
+                        log::info!("Chat connection to {addr} was closed. Reconnecting.");
+                        reported = false;
+                        state.status.send_replace(format!("Disconnected from {addr}. Reconnecting…"));
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                        continue;
+                    }
+                    Err(err) => err,
+                };
+                log::error!("Chat connection to {addr} failed: {err}");
+                state.status.send_replace(format!("Connection to {addr} failed: {err}. Retrying…"));
+                if !reported {
+                    reported = true;
+                    handle_display_popup_arc(
+                        &popups,
+                        format!("Could not connect to the chat at {addr}. Retrying every {} seconds.", RECONNECT_DELAY.as_secs()),
+                        &err,
+                        "Chat connection failed"
+                    );
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        });
+        Self {
+            config,
+            status: status_recv,
+            handle,
+        }
+    }
+    pub fn status(&self) -> String {
+        self.status.borrow().clone()
+    }
+    ///Applies config changes. Returns false, if the client needs to be restarted for them.
+    pub fn set_config(&self, config: &ChatConfig) -> bool {
+        let mut lock = self.config.write().unwrap_or_else(|err| err.into_inner());
+        if lock.connection() != config.connection() {
+            return false;
+        }
+        if *lock != *config {
+            lock.clone_from(config);
+        }
+        true
+    }
+}
+
+impl Drop for Chat {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn send(writer: &mut OwnedWriteHalf, line: &str) -> std::io::Result<()> {
+    writer.write_all(line.as_bytes()).await?;
+    writer.write_all(b"\r\n").await
+}
+
+///A chat command, that is applied without holding up the connection.
+struct Request {
+    text: String,
+    command: Result<(Arc<str>, Operation), String>,
+}
+
+///Sends `lines` right away and `replies` at most once every [`REPLY_DELAY`].
+///Ends, once no more lines can be sent.
+async fn write_lines(writer: &mut OwnedWriteHalf, mut lines: mpsc::UnboundedReceiver<String>, mut replies: mpsc::Receiver<String>) -> std::io::Result<()> {
+    let mut next_reply = Instant::now();
+    let mut waiting = false;
+    loop {
+        tokio::select! {
+            biased;
+            line = lines.recv() => match line {
+                Some(line) => send(writer, &line).await?,
+                None => return Ok(()),
+            },
+            () = tokio::time::sleep_until(next_reply), if waiting => waiting = false,
+            Some(reply) = replies.recv(), if !waiting => {
+                send(writer, &reply).await?;
+                next_reply = Instant::now() + REPLY_DELAY;
+                waiting = true;
+            }
+        }
+    }
+}
+
+///Applies commands one after another and queues the replies.
+async fn handle_requests(state: &ChatState, channel: &str, mut requests: mpsc::UnboundedReceiver<Request>, replies: mpsc::Sender<String>) -> std::io::Result<()> {
+    while let Some(Request { text, command }) = requests.recv().await {
+        let result = match command {
+            Ok((counter, operation)) => state.commands.request(counter, operation).await.map_err(|err| err.to_string()),
+            Err(usage) => Err(usage),
+        };
+        if let Err(err) = result {
+            log::warn!("Chat: '{text}' failed: {err}");
+            if state.config().reply && replies.try_send(format!("PRIVMSG {channel} :{err}")).is_err() {
+                log::warn!("Chat: too many replies are waiting. Not answering '{text}'.");
+            }
+        }
+    }
+    Ok(())
+}
+
+///Handles one connection, until the server closes it.
+async fn run(state: &ChatState) -> std::io::Result<()> {
+    let config = state.config();
+    let channel = config.channel();
+    let (reader, mut writer) = TcpStream::connect((config.host.as_str(), config.port)).await?.into_split();
+    if !config.password.is_empty() {
+        send(&mut writer, &format!("PASS {}", config.password)).await?;
+    }
+    send(&mut writer, &format!("NICK {}", config.nick)).await?;
+    send(&mut writer, &format!("USER {} 0 * :{}", config.nick, crate::APP_NAME)).await?;
+    //Twitch only tells us about moderators with tags. Other servers ignore this.
+    send(&mut writer, "CAP REQ :twitch.tv/tags").await?;
+    send(&mut writer, &format!("JOIN {channel}")).await?;
+    state.status.send_replace(format!("Connected to {channel} on {}", config.host));
+    log::info!("Chat connected to {channel} on {}:{}", config.host, config.port);
+    let (line_send, line_recv) = mpsc::unbounded_channel();
+    let (reply_send, reply_recv) = mpsc::channel(MAX_QUEUED_REPLIES);
+    let (request_send, request_recv) = mpsc::unbounded_channel();
+    //Reading never waits for commands or replies, so that PINGs are always answered in time.
+    let read = async {
+        //Owned by the reader, so that closing the connection closes the channels and ends the other parts
+        let (line_send, request_send) = (line_send, request_send);
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            let Some(message) = Message::parse(&line) else {
+                continue;
+            };
+            match message.command {
+                "PING" => {
+                    let _ = line_send.send(format!("PONG :{}", message.trailing().unwrap_or_default()));
+                }
+                "PRIVMSG" => {
+                    let (Some(target), Some(text)) = (message.params.first(), message.trailing()) else {
+                        continue;
+                    };
+                    //Whispers and other channels
+                    if !target.eq_ignore_ascii_case(&channel) {
+                        continue;
+                    }
+                    let config = state.config();
+                    let Some(command) = config.parse_command(text) else {
+                        continue;
+                    };
+                    if !config.is_allowed(&message) {
+                        log::info!("Chat: ignored '{text}' from {:?}, who is not allowed to use commands", message.nick());
+                        continue;
+                    }
+                    if command.is_ok() {
+                        log::info!("Chat: {:?} used '{text}'", message.nick());
+                    }
+                    let command = command.map(|(counter, operation)| (Arc::from(counter), operation));
+                    let _ = request_send.send(Request { text: text.to_string(), command });
+                }
+                _ => {}
+            }
+        }
+        Ok::<(), std::io::Error>(())
+    };
+    tokio::try_join!(
+        read,
+        handle_requests(state, &channel, request_recv, reply_send),
+        write_lines(&mut writer, line_recv, reply_recv),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+    use tokio::net::tcp::OwnedReadHalf;
+    use tokio::net::TcpListener;
+    use crate::app::command::{CommandChannel, CommandError};
+    use super::*;
+
+    const NANOS: i64 = 1_000_000_000;
+
+    fn config() -> ChatConfig {
+        ChatConfig {
+            channel: "Streamer".to_string(),
+            commands: vec![ChatCommand { prefix: "!timer".to_string(), counter: "Timer".to_string() }],
+            allowed_users: "alice, Bob".to_string(),
+            ..ChatConfig::default()
+        }
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("5m"), Some(300.));
+        assert_eq!(parse_duration("1h30m"), Some(5400.));
+        assert_eq!(parse_duration("1.5s"), Some(1.5));
+        assert_eq!(parse_duration("-90"), Some(-90.));
+        assert_eq!(parse_duration("1:30"), Some(90.));
+        for invalid in ["", "m", "5m30", "xyz", "5x", "1.2.3m", "1:x", "5 m", "--5"] {
+            assert_eq!(parse_duration(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let config = config();
+        assert_eq!(config.parse_command("hello"), None);
+        assert_eq!(config.parse_command("!TIMER Start"), Some(Ok(("Timer", Operation::Start))));
+        assert_eq!(config.parse_command("!timer pause"), Some(Ok(("Timer", Operation::Stop))));
+        assert_eq!(config.parse_command("!timer reset"), Some(Ok(("Timer", Operation::Reset))));
+        assert_eq!(config.parse_command("!timer add 5m"), Some(Ok(("Timer", Operation::Add(300 * NANOS)))));
+        assert_eq!(config.parse_command("!timer sub 1:00"), Some(Ok(("Timer", Operation::Add(-60 * NANOS)))));
+        assert_eq!(config.parse_command("!timer set 10"), Some(Ok(("Timer", Operation::Set(10 * NANOS)))));
+        for invalid in ["!timer", "!timer jump", "!timer add", "!timer add 5m30", "!timer set abc"] {
+            assert!(matches!(config.parse_command(invalid), Some(Err(usage)) if usage.starts_with("Usage: !timer")), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn permissions() {
+        let mut config = config();
+        let allowed = |config: &ChatConfig, line: &str| config.is_allowed(&Message::parse(line).unwrap());
+        assert!(allowed(&config, ":ALICE!alice@host PRIVMSG #streamer :!timer start"));
+        assert!(allowed(&config, ":bob!bob@host PRIVMSG #streamer :!timer start"));
+        assert!(allowed(&config, ":streamer!streamer@host PRIVMSG #streamer :!timer start"));
+        assert!(allowed(&config, "@badges=moderator/1;mod=0 :carol!carol@host PRIVMSG #streamer :!timer start"));
+        assert!(allowed(&config, "@mod=1 :dave!dave@host PRIVMSG #streamer :!timer start"));
+        assert!(allowed(&config, "@badges=subscriber/12,broadcaster/1 :erin!erin@host PRIVMSG #streamer :!timer start"));
+        assert!(!allowed(&config, "@badges=subscriber/12;mod=0 :mallory!mallory@host PRIVMSG #streamer :!timer start"));
+        assert!(!allowed(&config, "PRIVMSG #streamer :!timer start"));
+        config.allow_moderators = false;
+        assert!(!allowed(&config, "@mod=1 :dave!dave@host PRIVMSG #streamer :!timer start"));
+        assert!(!allowed(&config, ":streamer!streamer@host PRIVMSG #streamer :!timer start"));
+        assert!(allowed(&config, ":alice!alice@host PRIVMSG #streamer :!timer start"));
+    }
+
+    async fn expect(lines: &mut Lines<BufReader<OwnedReadHalf>>, expected: &str) {
+        let line = tokio::time::timeout(Duration::from_secs(5), lines.next_line()).await.unwrap().unwrap();
+        assert_eq!(line.as_deref(), Some(expected));
+    }
+
+    ///Talks to the client like an irc server would.
+    #[tokio::test]
+    async fn irc_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut channel = CommandChannel::default();
+        let state = ChatState {
+            commands: channel.sender(),
+            config: Arc::new(RwLock::new(ChatConfig {
+                host: "127.0.0.1".to_string(),
+                port: listener.local_addr().unwrap().port(),
+                nick: "bot".to_string(),
+                password: "oauth:secret".to_string(),
+                ..config()
+            })),
+            status: Arc::new(watch::channel(String::new()).0),
+        };
+        let client = tokio::spawn(async move { run(&state).await });
+        let (socket, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = socket.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        expect(&mut lines, "PASS oauth:secret").await;
+        expect(&mut lines, "NICK bot").await;
+        expect(&mut lines, &format!("USER bot 0 * :{}", crate::APP_NAME)).await;
+        expect(&mut lines, "CAP REQ :twitch.tv/tags").await;
+        expect(&mut lines, "JOIN #streamer").await;
+
+        writer.write_all(b"PING :tmi.twitch.tv\r\n").await.unwrap();
+        expect(&mut lines, "PONG :tmi.twitch.tv").await;
+
+        //Not allowed, so it is ignored
+        writer.write_all(b"@mod=0 :mallory!mallory@host PRIVMSG #streamer :!timer reset\r\n").await.unwrap();
+        writer.write_all(b"@mod=1 :dave!dave@host PRIVMSG #streamer :!timer add 5m\r\n").await.unwrap();
+        let command = channel.recv().await.unwrap();
+        assert_eq!(command.counter.as_ref(), "Timer");
+        assert_eq!(command.operation, Operation::Add(300 * NANOS));
+        //A command, that isn't answered yet, doesn't hold up PINGs
+        writer.write_all(b"PING :again\r\n").await.unwrap();
+        expect(&mut lines, "PONG :again").await;
+        command.reply.unwrap().send(Err(CommandError::UnknownCounter(command.counter.clone()))).unwrap();
+        expect(&mut lines, "PRIVMSG #streamer :There is no Counter named 'Timer'").await;
+
+        //Whispers and other channels are ignored
+        writer.write_all(b":alice!alice@host PRIVMSG bot :!timer start\r\n").await.unwrap();
+        writer.write_all(b":alice!alice@host PRIVMSG #other :!timer start\r\n").await.unwrap();
+        writer.write_all(b":alice!alice@host PRIVMSG #streamer :!timer add soon\r\n").await.unwrap();
+        expect(&mut lines, "PRIVMSG #streamer :Usage: !timer start|stop|reset|add <time>|sub <time>|set <time>").await;
+        assert!(channel.try_recv().is_none());
+
+        drop(writer);
+        drop(lines);
+        client.await.unwrap().unwrap();
+    }
+}
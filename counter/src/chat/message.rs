@@ -0,0 +1,106 @@
+///An irc line like `@tags :nick!user@host COMMAND param :trailing`, borrowed from the line.
+pub(super) struct Message<'a> {
+    tags: Option<&'a str>,
+    source: Option<&'a str>,
+    pub command: &'a str,
+    ///Includes the trailing parameter as last element
+    pub params: Vec<&'a str>,
+    has_trailing: bool,
+}
+
+impl<'a> Message<'a> {
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+        let mut tags = None;
+        if let Some(tagged) = rest.strip_prefix('@') {
+            let (value, remaining) = tagged.split_once(' ')?;
+            tags = Some(value);
+            rest = remaining.trim_start();
+        }
+        let mut source = None;
+        if let Some(sourced) = rest.strip_prefix(':') {
+            let (value, remaining) = sourced.split_once(' ')?;
+            source = Some(value);
+            rest = remaining.trim_start();
+        }
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() {
+            return None;
+        }
+        let mut params = Vec::new();
+        let mut has_trailing = false;
+        while !rest.is_empty() {
+            if let Some(trailing) = rest.strip_prefix(':') {
+                params.push(trailing);
+                has_trailing = true;
+                break;
+            }
+            let (param, remaining) = rest.split_once(' ').unwrap_or((rest, ""));
+            params.push(param);
+            rest = remaining.trim_start();
+        }
+        Some(Self { tags, source, command, params, has_trailing })
+    }
+    pub fn trailing(&self) -> Option<&'a str> {
+        self.params.last().copied().filter(|_| self.has_trailing)
+    }
+    ///The nick of the sender
+    pub fn nick(&self) -> Option<&'a str> {
+        let source = self.source?;
+        Some(source.split_once('!').map_or(source, |(nick, _)| nick))
+    }
+    ///Tag values are returned unescaped, which is enough for comparing them with simple values.
+    pub fn tag(&self, name: &str) -> Option<&'a str> {
+        self.tags?
+            .split(';')
+            .find_map(|tag| match tag.split_once('=') {
+                Some((key, value)) if key == name => Some(value),
+                None if tag == name => Some(""),
+                _ => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn twitch_privmsg() {
+        let message = Message::parse("@badge-info=;badges=moderator/1;display-name=Alice;mod=1 :alice!alice@alice.tmi.twitch.tv PRIVMSG #chan :!timer add 5m\r\n").unwrap();
+        assert_eq!(message.command, "PRIVMSG");
+        assert_eq!(message.params, ["#chan", "!timer add 5m"]);
+        assert_eq!(message.trailing(), Some("!timer add 5m"));
+        assert_eq!(message.nick(), Some("alice"));
+        assert_eq!(message.tag("mod"), Some("1"));
+        assert_eq!(message.tag("badge-info"), Some(""));
+        assert_eq!(message.tag("badges"), Some("moderator/1"));
+        assert_eq!(message.tag("color"), None);
+    }
+
+    #[test]
+    fn without_prefixes() {
+        let message = Message::parse("PING :tmi.twitch.tv").unwrap();
+        assert_eq!(message.command, "PING");
+        assert_eq!(message.trailing(), Some("tmi.twitch.tv"));
+        assert_eq!(message.nick(), None);
+        assert_eq!(message.tag("mod"), None);
+
+        let message = Message::parse(":server 001 bot welcome").unwrap();
+        assert_eq!(message.command, "001");
+        assert_eq!(message.params, ["bot", "welcome"]);
+        assert_eq!(message.trailing(), None);
+        assert_eq!(message.nick(), Some("server"));
+
+        let message = Message::parse("PRIVMSG #chan ::)").unwrap();
+        assert_eq!(message.trailing(), Some(":)"));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Message::parse("").is_none());
+        assert!(Message::parse("@tags-only").is_none());
+        assert!(Message::parse(":source-only").is_none());
+        assert!(Message::parse("@mod=1 :nick ").is_none());
+    }
+}
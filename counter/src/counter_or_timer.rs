@@ -1,37 +1,44 @@
 use std::fmt::{Display, Formatter};
-use std::io::SeekFrom;
 use std::ops::Add;
-use std::path::PathBuf;
 use std::str::FromStr;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
 use std::time::Duration;
-use chrono::Timelike;
+use chrono::{DateTime, Timelike, Utc};
 use egui::{Response, Ui, Widget};
 use rfd::FileHandle;
 use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::watch;
 use tokio::task::JoinHandle;
 use tokio::time::{Instant, MissedTickBehavior};
 use crate::app::popup;
 use crate::app::popup::{handle_display_popup_arc, popup_creator};
+use crate::app::command::CommandSender;
+use crate::counter_or_timer::action::{ActionContext, EventAction, Trigger};
+use crate::counter_or_timer::adjust::{default_adjustments, TaskMsg};
+use crate::counter_or_timer::deadline::Deadline;
+use crate::counter_or_timer::format::{Template, Values};
+use crate::counter_or_timer::output::{OutputConfig, Outputs, WriteStatus};
 use crate::get_runtime;
 
+pub(crate) mod format;
+mod deadline;
+mod action;
+pub(crate) mod output;
+pub(crate) mod spec;
+mod adjust;
+mod obs;
+
 const SECONDS_IN_MINUTE: u64 = 60;
 const MINUTES_IN_HOUR: u64 = 60;
 const HOURS_IN_DAY: u64 = 24;
 const SECONDS_IN_DAY: u64 = SECONDS_IN_MINUTE * MINUTES_IN_HOUR * HOURS_IN_DAY;
-fn sec_to_timestamp(sec: i64) -> (u64, u64, u64, bool){
-    let neg = sec.is_negative();
-    let sec = sec.unsigned_abs();
-    let s = sec%SECONDS_IN_MINUTE;
-    let m = sec/SECONDS_IN_MINUTE;
-    let h = m/MINUTES_IN_HOUR;
-    let m  = m%MINUTES_IN_HOUR;
-    (s, m, h, neg)
-}
+const MILLIS_IN_SECOND: i64 = 1000;
+const NANOS_IN_MILLI: i64 = 1_000_000;
+pub(crate) const NANOS_IN_SECOND: i64 = NANOS_IN_MILLI * MILLIS_IN_SECOND;
+const NANOS_IN_DAY: i64 = SECONDS_IN_DAY as i64 * NANOS_IN_SECOND;
 
-const MODES:&[Mode] = &[Mode::Counter, Mode::Timer, Mode::SystemTime];
+const MODES:&[Mode] = &[Mode::Counter, Mode::Timer, Mode::SystemTime, Mode::Deadline, Mode::Tally];
 
 #[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub enum Mode {
@@ -42,6 +49,10 @@ pub enum Mode {
     Timer,
     //Matches system time
     SystemTime,
+    //Counts Down to a date and time
+    Deadline,
+    //Holds a whole number, that is changed by hand
+    Tally,
 }
 
 impl Mode{
@@ -50,36 +61,45 @@ impl Mode{
             Self::Counter => "Time to Start Counting Up from:",
             Self::Timer => "Time to Start Counting Down from:",
             Self::SystemTime => "Time to add to Current Local Time:",
+            Self::Deadline => "Date and Time to Count Down to:",
+            Self::Tally => "Count to Start from:",
         }
     }
-    pub fn get_timestamp(self, s: &Arc<AtomicI64>, mut start_sec: i64, overall_change: Duration) -> (u64, u64, u64, bool, bool){
+    ///Returns the nanoseconds to display and whether the value has hit the limits of an i64.
+    ///`deadline` is only used by [`Mode::Deadline`].
+    pub fn get_timestamp(self, s: &Arc<AtomicI64>, start_ns: i64, overall_change: Duration, deadline: Option<DateTime<Utc>>) -> (i64, bool){
+        let change = i64::try_from(overall_change.as_nanos()).ok();
         match self{
             Self::Timer =>  {
-                let dur = start_sec.checked_sub_unsigned(overall_change.as_secs());
+                let dur = change.and_then(|change| start_ns.checked_sub(change));
                 let maxed = dur.is_none();
                 let dur = dur.unwrap_or(i64::MIN);
                 s.store(dur, Ordering::Release);
-                let (s, m, h, neg) = sec_to_timestamp(dur);
-                (s, m, h, neg, maxed)
+                (dur, maxed)
             },
             Self::Counter => {
-                let dur = start_sec.checked_add_unsigned(overall_change.as_secs());
+                let dur = change.and_then(|change| start_ns.checked_add(change));
                 let maxed = dur.is_none();
                 let dur = dur.unwrap_or(i64::MAX);
                 s.store(dur, Ordering::Release);
-                let (s, m, h, neg) = sec_to_timestamp(dur);
-                (s, m, h, neg, maxed)
+                (dur, maxed)
             },
             Self::SystemTime => {
-                start_sec = start_sec % SECONDS_IN_DAY as i64;
-                debug_assert!(chrono::TimeDelta::try_seconds(SECONDS_IN_DAY as i64).is_some());
-                let timedelta = chrono::TimeDelta::try_seconds(start_sec).unwrap_or_else(||{
-                    log::error!("How did we get here? Apparently {SECONDS_IN_DAY}*1000>{0} or -{SECONDS_IN_DAY}*1000 > -{0}", i64::MAX);
-                    chrono::TimeDelta::nanoseconds(0)
-                });
+                let timedelta = chrono::TimeDelta::nanoseconds(start_ns % NANOS_IN_DAY);
                 let time = chrono::Local::now().time().overflowing_add_signed(timedelta).0;
-                (u64::from(time.second()), u64::from(time.minute()), u64::from(time.hour()), false, false)
+                (i64::from(time.num_seconds_from_midnight()) * NANOS_IN_SECOND + i64::from(time.nanosecond()), false)
             },
+            Self::Deadline => {
+                //Always recompute from the wall clock, so that pauses, restarts or a sleeping pc don't matter.
+                let remaining = deadline.map_or(Some(0), |deadline| (deadline - Utc::now()).num_nanoseconds());
+                let maxed = remaining.is_none();
+                //Round towards the deadline, like the Timer does
+                let dur = remaining.map_or(i64::MAX, |remaining| -(-remaining).div_euclid(NANOS_IN_MILLI) * NANOS_IN_MILLI);
+                s.store(dur, Ordering::Release);
+                (dur, maxed)
+            },
+            //Only changed by the increment and decrement buttons
+            Self::Tally => (s.load(Ordering::Acquire), false),
         }
     }
 
@@ -91,63 +111,367 @@ impl Display for Mode{
             Self::Counter => write!(f, "Counter (Up)"),
             Self::Timer => write!(f, "Timer (Down)"),
             Self::SystemTime => write!(f, "SystemTime"),
+            Self::Deadline => write!(f, "Countdown to Date"),
+            Self::Tally => write!(f, "Tally"),
+        }
+    }
+}
+
+const AT_ZERO:&[AtZero] = &[AtZero::Continue, AtZero::Stop, AtZero::Overtime, AtZero::Restart];
+
+///What a [`Mode::Timer`] does, once it reaches zero.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, Eq, PartialEq, Hash)]
+pub enum AtZero {
+    //Keeps counting into negative numbers
+    #[default]
+    Continue,
+    //Stays at zero and stops the Timer
+    Stop,
+    //Counts the time past zero with a '+' sign
+    Overtime,
+    //Starts from the start value again, a configured amount of times. Stops afterward.
+    Restart,
+}
+
+impl AtZero {
+    ///Applies this behaviour to the value of a Timer.
+    ///Returns the value to show and whether the Timer should stop.
+    fn apply(self, nanos: i64, start_ns: &mut i64, initial_ns: i64, restarts_left: &AtomicU32) -> (i64, bool) {
+        if nanos > 0 {
+            return (nanos, false);
+        }
+        match self {
+            Self::Continue | Self::Overtime => (nanos, false),
+            Self::Stop => (0, true),
+            Self::Restart => {
+                //A start value of zero or below would restart forever, without showing anything
+                if initial_ns <= 0 {
+                    return (0, true);
+                }
+                //Every restart adds the start value. A long pause can pass zero several times at once.
+                let needed = u32::try_from(nanos.unsigned_abs() / initial_ns.unsigned_abs() + 1).unwrap_or(u32::MAX);
+                let Ok(left) = restarts_left.fetch_update(Ordering::AcqRel, Ordering::Acquire, |left| {
+                    (left > 0).then(|| left.saturating_sub(needed))
+                }) else {
+                    return (0, true);
+                };
+                //Carry the time past zero over, so that no time gets lost
+                let carried = initial_ns.saturating_mul(i64::from(left.min(needed)));
+                *start_ns = start_ns.saturating_add(carried);
+                if left < needed {
+                    return (0, true);
+                }
+                (nanos.saturating_add(carried), false)
+            }
         }
     }
 }
 
+impl Display for AtZero{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Continue => write!(f, "Continue into negative"),
+            Self::Stop => write!(f, "Stop at zero"),
+            Self::Overtime => write!(f, "Count overtime (+)"),
+            Self::Restart => write!(f, "Restart"),
+        }
+    }
+}
+
+///Identifies a counter, independent of its editable name.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[serde(transparent)]
+pub struct CounterId(pub u64);
+
+impl Display for CounterId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+///What others (e.g. the http server) can see of a counter.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Status {
+    pub name: Arc<str>,
+    pub mode: Mode,
+    pub running: bool,
+    pub value_ns: i64,
+    ///The value in the counter's format
+    pub text: String,
+    pub outputs: Vec<WriteStatus>,
+}
+
+///Where a running counter started, so that it can continue after the app restarts.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+struct RunningSince {
+    since: DateTime<Utc>,
+    ///The value at `since`
+    start_ns: i64,
+    restarts_left: u32,
+}
+
+fn default_status() -> watch::Sender<Status> {
+    watch::channel(Status::default()).0
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct CounterTimer {
+    ///The key in the app's counters. Set by the app after loading.
+    #[serde(skip)]
+    pub id: CounterId,
     pub name: Arc<str>,
     mode: Mode,
-    file: PathBuf,
-    time_s: Arc<AtomicI64>,
+    #[serde(default)]
+    outputs: Vec<OutputConfig>,
+    ///The live value in nanoseconds.
+    ///A [`Mode::Tally`] keeps its count here instead, so that every mode shares the task, outputs and actions.
+    #[serde(default)]
+    time_ns: Arc<AtomicI64>,
+    ///The configured value to start from. `time_ns` is the live value.
+    ///The count to start from for a [`Mode::Tally`].
+    #[serde(default)]
+    initial_ns: i64,
+    #[serde(default = "default_period")]
+    period: Duration,
+    #[serde(default = "default_format")]
+    format: String,
+    #[serde(default)]
+    deadline: Deadline,
+    ///Only used by [`Mode::Timer`]
+    #[serde(default)]
+    at_zero: AtZero,
+    ///How often [`AtZero::Restart`] restarts the Timer
+    #[serde(default)]
+    restarts: u32,
+    ///How much the buttons of a [`Mode::Tally`] change the count
+    #[serde(default = "default_step")]
+    step: i64,
+    ///Nanoseconds the quick adjust buttons add or subtract
+    #[serde(default = "default_adjustments")]
+    adjustments: Vec<i64>,
+    #[serde(default)]
+    restarts_left: Arc<AtomicU32>,
+    #[serde(default)]
+    actions: Vec<EventAction>,
+    ///Set while the counter is running
+    #[serde(default)]
+    running: Option<RunningSince>,
+    #[serde(skip)]
+    actions_open: bool,
     #[serde(skip)]
-    file_pick: Option<JoinHandle<Option<FileHandle>>>,
+    adjust_open: bool,
     #[serde(skip)]
-    counter: Option<(tokio::sync::oneshot::Sender<()>, JoinHandle<()>)>,
+    outputs_open: bool,
+    ///The value entered in the adjust window
     #[serde(skip)]
-    pub(crate) popup: crate::app::popup::ArcPopupStore
+    adjust_ns: i64,
+    #[serde(skip, default = "default_status")]
+    status: watch::Sender<Status>,
+    #[serde(skip)]
+    ///The output a file is being picked for
+    file_pick: Option<(usize, JoinHandle<Option<FileHandle>>)>,
+    #[serde(skip)]
+    counter: Option<(tokio::sync::mpsc::UnboundedSender<TaskMsg>, JoinHandle<()>)>,
+    ///Stopped tasks, that are still writing their outputs
+    #[serde(skip)]
+    stopping: Vec<JoinHandle<()>>,
+    ///Held by the running task, while it stores the value.
+    ///Set by [`CounterTimer::stop_counter`], which stores the final value itself. The task doesn't store anything afterward.
+    #[serde(skip)]
+    stopped: Arc<Mutex<bool>>,
+    ///The last write of a stopped counter, see [`CounterTimer::write_outputs`]
+    #[serde(skip)]
+    writing: Option<JoinHandle<()>>,
+    #[serde(skip)]
+    pub(crate) popup: crate::app::popup::ArcPopupStore,
+    #[serde(skip)]
+    pub(crate) commands: CommandSender,
+}
+
+///Formats seconds as `[-]HH:MM:SS.mmm`
+fn format_seconds(sec: f64) -> String {
+    let neg = sec.is_sign_negative();
+    //Saturating conversion. Rounding, so that 0.1 doesn't become 0.099
+    let ms = (sec.abs() * MILLIS_IN_SECOND as f64).round() as u64;
+    let s = ms / MILLIS_IN_SECOND as u64;
+    let ms = ms % MILLIS_IN_SECOND as u64;
+    let min = s / SECONDS_IN_MINUTE;
+    let s = s % SECONDS_IN_MINUTE;
+    let hr = min / MINUTES_IN_HOUR;
+    let min  = min % MINUTES_IN_HOUR;
+    format!("{0}{hr:02}:{min:02}:{s:02}.{ms:03}", if neg {"-"} else {""})
+}
+///Saturating conversion
+pub(crate) fn seconds_to_nanos(sec: f64) -> i64 {
+    (sec * NANOS_IN_SECOND as f64) as i64
+}
+pub(crate) fn format_nanos(ns: i64) -> String {
+    format_seconds(ns as f64 / NANOS_IN_SECOND as f64)
+}
+///Parses `[-][[[[years:]months:]days:]hours:]minutes:]seconds` into seconds.
+///Returns `None`, if any part isn't a plain non-negative number.
+pub(crate) fn parse_seconds(string: &str) -> Option<f64> {
+    let string = string.trim();
+    let neg = string.strip_prefix("-");
+    let vec = neg.unwrap_or(string).rsplit(":").collect::<Vec<_>>();
+    let mut seconds = 0.;
+    let mut conversion = 1.;
+    for (loops, string) in vec.iter().enumerate() {
+        //f64::from_str would also take signs, "inf" and "NaN"
+        if string.is_empty() || !string.chars().all(|c| c.is_ascii_digit() || c == '.') {
+            return None;
+        }
+        seconds += conversion * f64::from_str(string).ok()?;
+        conversion *= match loops {
+            //Seconds->Minutes && Minutes->Hours
+            0 | 1 => 60.,
+            //Hours->Days
+            2 => 24.,
+            //Days->Months
+            //365.2425 is the average Year length in Days of the Gregorian calendar
+            3 => 365.2425/12.,
+            //Months->Years
+            4 => 12.,
+            //I don't care beyond this point. tbh everything past days is already extra
+            _ => 1000.,
+        };
+    }
+    if neg.is_some() {
+        seconds = -seconds;
+    }
+    Some(seconds)
+}
+
+fn default_format() -> String {
+    format::DEFAULT_TEMPLATE.to_string()
+}
+const fn default_period() -> Duration {
+    crate::PERIOD
+}
+const fn default_step() -> i64 {
+    1
 }
 
 impl CounterTimer {
-    pub(crate) fn new(name: Arc<str>, popup: crate::app::popup::ArcPopupStore) -> Self {
+    pub(crate) fn new(id: CounterId, name: Arc<str>, popup: crate::app::popup::ArcPopupStore, commands: CommandSender) -> Self {
         Self{
+            id,
             name,
             mode: Mode::default(),
-            file: Default::default(),
-            time_s: Arc::new(AtomicI64::new(0)),
+            outputs: vec![OutputConfig::default()],
+            time_ns: Arc::new(AtomicI64::new(0)),
+            initial_ns: 0,
+            period: default_period(),
+            format: default_format(),
+            deadline: Deadline::default(),
+            at_zero: AtZero::default(),
+            restarts: 0,
+            step: default_step(),
+            adjustments: default_adjustments(),
+            restarts_left: Arc::new(AtomicU32::new(0)),
+            actions: Vec::new(),
+            running: None,
+            actions_open: false,
+            adjust_open: false,
+            outputs_open: false,
+            adjust_ns: 0,
+            status: default_status(),
             file_pick: None,
             counter: None,
+            stopping: Vec::new(),
+            stopped: Arc::default(),
+            writing: None,
             popup,
+            commands,
         }
     }
 
+    pub fn subscribe(&self) -> watch::Receiver<Status> {
+        self.status.subscribe()
+    }
+    ///Updates the parts of the [`Status`], that the counter task doesn't know about.
+    pub fn sync_status(&mut self) {
+        self.check_counter();
+        let running = self.counter.is_some();
+        self.status.send_if_modified(|status| {
+            let mut modified = false;
+            if status.name != self.name {
+                status.name = self.name.clone();
+                modified = true;
+            }
+            if status.mode != self.mode {
+                status.mode = self.mode;
+                modified = true;
+            }
+            if running {
+                //The counter task keeps the value up to date
+                return modified;
+            }
+            if status.running {
+                status.running = false;
+                modified = true;
+            }
+            let value_ns = self.time_ns.load(Ordering::Acquire);
+            //Only render on changes, so that texts written by actions stay
+            if modified || status.value_ns != value_ns {
+                status.value_ns = value_ns;
+                status.text = Template::from_str(&self.format)
+                    .map(|template| template.render(&Values{name: &self.name, nanos: value_ns, overtime: self.mode == Mode::Timer && self.at_zero == AtZero::Overtime}))
+                    .unwrap_or_default();
+                modified = true;
+            }
+            modified
+        });
+    }
+    ///How often the ui needs to be redrawn to show every change of this counter.
+    pub fn repaint_after(&self) -> Option<Duration> {
+        self.counter.as_ref().map(|_| self.period)
+    }
+
+    fn join_counter(&self, handle: JoinHandle<()>){
+        match get_runtime().block_on(handle) {
+            Ok(()) => {}
+            Err(err) => {
+                log::error!("Counter Thread Paniced: {err}");
+                handle_display_popup_arc(
+                    &self.popup,
+                    "An internal Error occurred",
+                    &err,
+                    "Error in Timer"
+                );
+            }
+        }
+    }
     fn check_counter(&mut self){
+        let (finished, stopping) = core::mem::take(&mut self.stopping).into_iter().partition(JoinHandle::is_finished);
+        self.stopping = stopping;
+        for handle in finished {
+            self.join_counter(handle);
+        }
         if let Some((sender, handle)) = self.counter.take(){
             if handle.is_finished(){
-                match get_runtime().block_on(handle) {
-                    Ok(()) => {}
-                    Err(err) => {
-                        log::error!("Counter Thread Paniced: {err}");
-                        handle_display_popup_arc(
-                            &self.popup,
-                            "An internal Error occurred",
-                            &err,
-                            "Error in Timer"
-                        );
-                    }
-                }
+                self.join_counter(handle);
+                self.running = None;
             }else{
                 self.counter = Some((sender, handle))
             }
         }
     }
+    fn pick_file(&mut self, output: usize) {
+        if let Some((_, picker)) = self.file_pick.take(){
+            picker.abort();
+        }
+        self.file_pick = Some((output, tokio::spawn(rfd::AsyncFileDialog::default().set_directory(".").pick_file())));
+    }
     fn check_file_pick(&mut self) {
-        if let Some(task) = self.file_pick.take() {
+        if let Some((output, task)) = self.file_pick.take() {
             if task.is_finished(){
                 match get_runtime().block_on(task){
                     Ok(Some(ok)) => {
-                        self.file = ok.path().to_path_buf();
+                        //The output might have been deleted or changed meanwhile
+                        if let Some(path) = self.outputs.get_mut(output).and_then(|output| output.destination.path_mut()) {
+                            *path = ok.path().to_path_buf();
+                        }
                     }
                     Ok(None) => {
                         log::info!("No File Selected.");
@@ -170,100 +494,339 @@ impl CounterTimer {
                     }
                 }
             }else{
-                self.file_pick = Some(task);
+                self.file_pick = Some((output, task));
             }
         }
     }
+    ///Stores the exact value at this instant, so that no fractions of a tick get lost, and stops the task.
+    ///Never waits for the task. It is collected by check_counter once it wrote its last outputs.
     pub fn stop_counter(&mut self){
         self.check_counter();
-        if let Some((sender,_)) = self.counter.take() {
-            match sender.send(()) {
+        if let Some((sender, handle)) = self.counter.take() {
+            {
+                let mut stopped = self.stopped.lock().unwrap_or_else(PoisonError::into_inner);
+                *stopped = true;
+                if let Some(nanos) = self.stop_value() {
+                    self.time_ns.store(nanos, Ordering::Release);
+                }
+            }
+            match sender.send(TaskMsg::Stop) {
                 Ok(()) => {}
-                Err(()) => {
+                Err(_) => {
                     log::info!("Counter {} has already exited early?", self.name.as_ref());
                 }
             }
+            self.stopping.push(handle);
+        }
+        self.running = None;
+    }
+    ///The value to keep, when stopping now. `None`, if the task doesn't store the value of this mode.
+    fn stop_value(&self) -> Option<i64> {
+        let running = self.running.as_ref()?;
+        match self.mode {
+            Mode::Counter | Mode::Deadline => Some(self.running_ns(running, self.deadline.resolve()).0),
+            Mode::Timer => {
+                let (raw, mut start_ns) = self.running_ns(running, None);
+                Some(self.at_zero.apply(raw, &mut start_ns, self.initial_ns, &self.restarts_left).0)
+            }
+            //The value doesn't depend on the time the counter ran
+            Mode::SystemTime | Mode::Tally => None,
+        }
+    }
+    ///Waits, until the stopped tasks wrote their last outputs. Only for shutting down.
+    pub fn wait_stopped(&mut self) {
+        for handle in core::mem::take(&mut self.stopping) {
+            self.join_counter(handle);
+        }
+    }
+    pub fn is_running(&mut self) -> bool {
+        self.check_counter();
+        self.counter.is_some()
+    }
+    ///Sets the start value, which is also used the next time the counter starts.
+    pub fn set_start_value(&mut self, nanos: i64){
+        self.initial_ns = nanos;
+        self.time_ns.store(nanos, Ordering::Release);
+    }
+    ///Changes the count of a [`Mode::Tally`] and writes it to the outputs right away, whether it's running or not.
+    pub fn step_tally(&mut self, steps: i64){
+        let delta = self.step.saturating_mul(steps);
+        self.check_counter();
+        let _ = self.time_ns.fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| Some(count.saturating_add(delta)));
+        match &self.counter {
+            Some((sender, _)) => {
+                //The task might have just finished. The next check notices that.
+                let _ = sender.send(TaskMsg::Refresh);
+            }
+            None => self.write_outputs(),
+        }
+    }
+    ///Writes the current value to every output once, without starting the counter.
+    ///Invalid formats are reported, once the counter is started.
+    fn write_outputs(&mut self){
+        let (Ok(template), Ok(mut outputs)) = (Template::from_str(&self.format), Outputs::new(&self.outputs, &self.name, &self.popup)) else {
+            return;
+        };
+        let name = self.name.clone();
+        let nanos = self.time_ns.load(Ordering::Acquire);
+        let overtime = self.mode == Mode::Timer && self.at_zero == AtZero::Overtime;
+        let popups = self.popup.clone();
+        let previous = self.writing.take();
+        self.writing = Some(tokio::spawn(async move {
+            //Quick presses must not overtake each other
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+            let values = Values{name: &name, nanos, overtime};
+            outputs.write(&template.render(&values), Some(&values), &popups).await;
+        }));
+    }
+    ///Goes back to the start value. A running counter keeps running from there.
+    pub fn reset_counter(&mut self){
+        self.check_counter();
+        let running = self.counter.is_some();
+        self.stop_counter();
+        self.time_ns.store(self.initial_ns, Ordering::Release);
+        self.restarts_left.store(self.restarts, Ordering::Release);
+        if running {
+            self.start_counter();
         }
     }
     pub fn start_counter(&mut self){
         if self.counter.is_some() {return;}
+        let running = RunningSince{
+            since: Utc::now(),
+            start_ns: self.time_ns.load(Ordering::Acquire),
+            restarts_left: self.restarts_left.load(Ordering::Acquire),
+        };
+        self.start_task(running, Duration::ZERO);
+    }
+    ///Continues a counter, that was running when the app was closed, as if it never stopped.
+    ///Returns `false`, if the counter wasn't running.
+    pub fn resume_counter(&mut self) -> bool {
+        let Some(running) = self.running.take() else {
+            return false;
+        };
+        if self.counter.is_none() {
+            self.time_ns.store(running.start_ns, Ordering::Release);
+            self.restarts_left.store(running.restarts_left, Ordering::Release);
+            //A clock, that went backwards, just means no time has passed.
+            let elapsed = (Utc::now() - running.since).to_std().unwrap_or_default();
+            log::info!("Resuming Counter {}, which started {} ago", self.name.as_ref(), format_nanos(i64::try_from(elapsed.as_nanos()).unwrap_or(i64::MAX)));
+            self.start_task(running, elapsed);
+        }
+        true
+    }
+    ///Forgets, that the counter was running when the app was closed.
+    pub fn discard_resume(&mut self) {
+        if self.counter.is_none() {
+            self.running = None;
+        }
+    }
+    ///`elapsed` is the time the counter already ran for, before `running.since`.
+    fn start_task(&mut self, running: RunningSince, elapsed: Duration){
+        let template = match Template::from_str(&self.format) {
+            Ok(template) => template,
+            Err(err) => {
+                log::warn!("Not starting Counter {} due to an invalid format: {err}", self.name.as_ref());
+                handle_display_popup_arc(
+                    &self.popup,
+                    "The Counter could not be started, because the format is invalid",
+                    &err,
+                    "Invalid Format"
+                );
+                return;
+            }
+        };
+        let mut outputs = match Outputs::new(&self.outputs, &self.name, &self.popup) {
+            Ok(outputs) => outputs,
+            Err((output, err)) => {
+                log::warn!("Not starting Counter {} due to an invalid format of {output}: {err}", self.name.as_ref());
+                handle_display_popup_arc(
+                    &self.popup,
+                    format!("The Counter could not be started, because the format of the output {output} is invalid"),
+                    &err,
+                    "Invalid Format"
+                );
+                return;
+            }
+        };
+        let deadline = self.deadline.resolve();
+        if self.mode == Mode::Deadline && deadline.is_none() {
+            log::warn!("Not starting Counter {} due to a nonexistent deadline", self.name.as_ref());
+            handle_display_popup_arc(
+                &self.popup,
+                "The Counter could not be started, because the date and time to count down to does not exist in the selected time zone",
+                &"The time is most likely skipped by a daylight saving time change.",
+                "Invalid Date and Time"
+            );
+            return;
+        }
+        let name = self.name.clone();
         let mode = self.mode;
-        let file = self.file.clone();
-        let s = self.time_s.clone();
-        let start_s = self.time_s.load(Ordering::Acquire);
+        let period = self.period;
+        let s = self.time_ns.clone();
+        let mut start_ns = self.time_ns.load(Ordering::Acquire);
+        let initial_ns = self.initial_ns;
+        let at_zero = if mode == Mode::Timer { Some(self.at_zero) } else { None };
+        let restarts_left = self.restarts_left.clone();
+        self.stopped = Arc::default();
+        let stopped = self.stopped.clone();
         let popups = self.popup.clone();
-        let (send, mut recv) = tokio::sync::oneshot::channel();
+        let (send, mut recv) = tokio::sync::mpsc::unbounded_channel();
+        //Taken before any file io, so that slow file systems don't delay the counter.
+        let start_instant = Instant::now();
+        //Where the task counts from, so that `running` stays in step with it (see `CounterTimer::current_ns`)
+        let since = chrono::Duration::from_std(elapsed).ok()
+            .and_then(|elapsed| Utc::now().checked_sub_signed(elapsed))
+            .unwrap_or(running.since);
+        let actions = self.actions.clone();
+        let commands = self.commands.clone();
+        let status = self.status.clone();
         let thread = tokio::spawn(async move {
-            let mut last_message = None;
-            let mut file = tokio::fs::OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .create(true)
-                .open(file.as_path())
-                .await
-                .map_or_else(|err|{
-                    log::error!("Failed opening file: {err}");
-                    crate::app::popup::handle_display_popup_arc(
-                        &popups,
-                        "Failed to get file to write the counter time to",
-                        &err,
-                        "Failed opening File"
-                    );
-                    None
-                }, |ok| Some(ok));
-            let start_instant = Instant::now();
-            let mut interval = tokio::time::interval_at(start_instant.add(crate::PERIOD), crate::PERIOD);
+            let overtime = at_zero == Some(AtZero::Overtime);
+            let run_actions = |reached: &dyn Fn(Trigger) -> bool, nanos: i64, text: &str| {
+                let ctx = ActionContext{name: &name, mode, nanos, text, popups: &popups, commands: &commands};
+                actions.iter()
+                    .filter(|action| reached(action.trigger))
+                    .filter_map(|action| action.run(&ctx))
+                    .last()
+            };
+            let text = template.render(&Values{name: &name, nanos: start_ns, overtime});
+            //Text to write instead of the value
+            let mut replacement = run_actions(&|trigger| trigger == Trigger::Start, start_ns, &text);
+            if let Some(replacement) = &replacement {
+                outputs.write(replacement, None, &popups).await;
+            }
+            //Only looks at the value. Storing is left to the ticks, which check whether the counter was stopped.
+            let mut prev = if elapsed.is_zero() {
+                start_ns
+            } else {
+                mode.get_timestamp(&Arc::new(AtomicI64::new(s.load(Ordering::Acquire))), start_ns, elapsed, deadline).0
+            };
+            //Whether a Tally wrote its count yet
+            let mut written = false;
+            let mut interval = tokio::time::interval_at(start_instant.add(period), period);
             interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
             loop{
                 tokio::select! {
                     biased;
-                    _ = &mut recv => {
-                        break
-                    }
+                    msg = recv.recv() => match msg {
+                        //Shifting the start shifts every following value
+                        Some(TaskMsg::Add(delta)) => start_ns = start_ns.saturating_add(delta),
+                        Some(TaskMsg::Set(target, at)) => {
+                            let (current, _) = mode.get_timestamp(&Arc::default(), start_ns, at - start_instant + elapsed, deadline);
+                            start_ns = start_ns.saturating_add(target.saturating_sub(current));
+                        }
+                        Some(TaskMsg::Refresh) => interval.reset_immediately(),
+                        //The ui already stored the final value
+                        Some(TaskMsg::Stop) | None => break,
+                    },
                     test = interval.tick() => {
-                        let overall_change = test - start_instant;
-                        let (s, m, h, neg, maxed) = mode.get_timestamp(&s, start_s, overall_change);
-                        if let Some(file) = file.as_mut() {
-                            if let Err(err) = file.seek(SeekFrom::Start(0)).await {
-                                if last_message.map_or(true, |insant: Instant|insant.elapsed().as_secs() > crate::NOTIFICATION_TIMEOUT){
-                                    last_message = Some(Instant::now());
-                                    log::error!("Error moving Cursor: {err}");
-                                    crate::app::popup::handle_display_popup_arc(
-                                        &popups,
-                                        "Could not make next write overwrite file",
-                                        &err,
-                                        "Error Seeking"
-                                    );
-                                }
-                            }else{
-                                if let Err(err) = file.write_all(format!("{0}{h:02}:{m:02}:{s:02}", if neg {"-"} else {""}).as_bytes()).await {
-                                    if last_message.map_or(true, |insant: Instant|insant.elapsed().as_secs() > crate::NOTIFICATION_TIMEOUT){
-                                        last_message = Some(Instant::now());
-                                        log::error!("Error writing to File: {err}");
-                                        crate::app::popup::handle_display_popup_arc(
-                                            &popups,
-                                            "Could not write to file",
-                                            &err,
-                                            "Error Writing"
-                                        );
-                                    }
-                                }
+                        let overall_change = test - start_instant + elapsed;
+                        let (raw, maxed, nanos, finished) = {
+                            let stopped = stopped.lock().unwrap_or_else(PoisonError::into_inner);
+                            if *stopped {
+                                break;
+                            }
+                            let (raw, maxed) = mode.get_timestamp(&s, start_ns, overall_change, deadline);
+                            let mut nanos = raw;
+                            let mut finished = false;
+                            if let Some(at_zero) = at_zero {
+                                (nanos, finished) = at_zero.apply(raw, &mut start_ns, initial_ns, &restarts_left);
+                                s.store(nanos, Ordering::Release);
                             }
+                            (raw, maxed, nanos, finished)
+                        };
+                        let values = Values{name: &name, nanos, overtime};
+                        let text = template.render(&values);
+                        let mut replaced = false;
+                        if let Some(text) = run_actions(&|trigger| trigger.reached(prev, raw), raw, &text) {
+                            replacement = Some(text);
+                            replaced = true;
                         }
+                        //A Tally only changes, when its buttons are pressed. Rewriting the same count every tick is pointless.
+                        let unchanged = mode == Mode::Tally && written && nanos == prev && !replaced;
+                        prev = nanos;
+                        if unchanged {
+                            continue;
+                        }
+                        written = true;
+                        match &replacement {
+                            Some(replacement) => outputs.write(replacement, None, &popups).await,
+                            None => outputs.write(&text, Some(&values), &popups).await,
+                        }
+                        let text = replacement.as_deref().unwrap_or(&text);
+                        status.send_modify(|status| {
+                            status.running = true;
+                            status.value_ns = nanos;
+                            text.clone_into(&mut status.text);
+                            status.outputs = outputs.status();
+                        });
                         if maxed {
                             handle_display_popup_arc(
                                 &popups,
                                 "A Timer has reached it's limits due to limitations of Computers",
-                                &"The Numeric Representation of the Timer in Seconds would overflow a signed 64-bit integer.",
+                                &"The Numeric Representation of the Timer in Nanoseconds would overflow a signed 64-bit integer.",
                                 "Reached timer limit",
                             );
                             break;
                         }
+                        if finished {
+                            log::info!("Timer {} reached zero and stopped", name.as_ref());
+                            break;
+                        }
                     }
                 }
             }
+            let nanos = s.load(Ordering::Acquire);
+            let text = template.render(&Values{name: &name, nanos, overtime});
+            let stop_text = run_actions(&|trigger| trigger == Trigger::Stop, nanos, &text);
+            if let Some(stop_text) = &stop_text {
+                outputs.write(stop_text, None, &popups).await;
+            }
+            let text = stop_text.or(replacement).unwrap_or(text);
+            status.send_modify(|status| {
+                status.running = false;
+                status.value_ns = nanos;
+                status.text = text;
+                status.outputs = outputs.status();
+            });
         });
         self.counter = Some((send, thread));
+        self.running = Some(RunningSince{since, ..running});
+    }
+}
+
+impl CounterTimer {
+    fn actions_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.actions_open;
+        egui::Window::new(format!("Actions of {}", self.name))
+            .id(egui::Id::new((self.id, "actions")))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                if self.counter.is_some() {
+                    ui.label("Stop the counter to edit its actions.");
+                }
+                ui.add_enabled_ui(self.counter.is_none(), |ui| {
+                    let mut remove = None;
+                    for (index, action) in self.actions.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            action.ui(ui, (self.id, index));
+                            if ui.button("Delete").clicked() {
+                                remove = Some(index);
+                            }
+                        });
+                    }
+                    if let Some(index) = remove {
+                        self.actions.remove(index);
+                    }
+                    if ui.button("Add Action").clicked() {
+                        self.actions.push(EventAction::default());
+                    }
+                });
+            });
+        self.actions_open = open;
     }
 }
 
@@ -271,15 +834,28 @@ impl Widget for &mut CounterTimer{
     fn ui(self, ui: &mut Ui) -> Response {
         self.check_counter();
         self.check_file_pick();
+        if self.actions_open {
+            self.actions_window(ui.ctx());
+        }
+        if self.adjust_open {
+            self.adjust_window(ui.ctx());
+        }
+        if self.outputs_open {
+            self.outputs_window(ui.ctx());
+        }
         ui.vertical(|ui|{
             ui.horizontal(|ui |{
-                ui.label("Current File: ");
-                ui.label(self.file.to_string_lossy());
-                if ui.button("Select File").clicked(){
-                    if let Some(picker) = self.file_pick.take(){
-                        picker.abort();
+                if ui.button(format!("Outputs ({})", self.outputs.len())).clicked() {
+                    self.outputs_open = !self.outputs_open;
+                }
+                let outputs = self.status.borrow().outputs.clone();
+                if let Some(last_write) = outputs.iter().filter_map(|output| output.last_write).max() {
+                    ui.label(format!("Last written: {}", last_write.with_timezone(&chrono::Local).format("%H:%M:%S")));
+                }
+                for output in outputs {
+                    if let Some(error) = output.error {
+                        ui.colored_label(ui.visuals().error_fg_color, format!("{} failed: {error}", output.output));
                     }
-                    self.file_pick = Some(tokio::spawn(rfd::AsyncFileDialog::default().set_directory(".").pick_file()));
                 }
             });
 
@@ -293,67 +869,263 @@ impl Widget for &mut CounterTimer{
                         self.stop_counter();
                     }
                 }
+                if ui.button("Reset").on_hover_text("Go back to the start value").clicked() {
+                    self.reset_counter();
+                }
+                if ui.button(format!("Actions ({})", self.actions.len())).clicked() {
+                    self.actions_open = !self.actions_open;
+                }
                 ui.add_enabled_ui(self.counter.is_none(), |ui| {
-                    egui::ComboBox::new(self.name.as_ref(), "")
+                    egui::ComboBox::from_id_source((self.id, "mode"))
                         .selected_text(self.mode.to_string())
                         .show_ui(
                             ui,
                             |ui| for mode in MODES {
-                                ui.selectable_value(&mut self.mode, *mode, mode.to_string());
+                                if ui.selectable_value(&mut self.mode, *mode, mode.to_string()).changed() {
+                                    //Time tokens make no sense for a count and the other way around
+                                    if self.mode == Mode::Tally && self.format == format::DEFAULT_TEMPLATE {
+                                        self.format = format::TALLY_TEMPLATE.to_string();
+                                    } else if self.mode != Mode::Tally && self.format == format::TALLY_TEMPLATE {
+                                        self.format = default_format();
+                                    }
+                                }
                             }
                         );
                 });
+                if self.mode == Mode::Tally {
+                    ui.label(format!("Current: {}", self.time_ns.load(Ordering::Acquire)));
+                    if ui.button(format!("-{}", self.step)).clicked() {
+                        self.step_tally(-1);
+                    }
+                    if ui.button(format!("+{}", self.step)).clicked() {
+                        self.step_tally(1);
+                    }
+                    ui.label("Step:");
+                    egui::DragValue::new(&mut self.step).clamp_range(1..=i64::MAX).ui(ui);
+                }
+                if matches!(self.mode, Mode::Counter | Mode::Timer) {
+                    let nanos = self.time_ns.load(Ordering::Acquire);
+                    if self.mode == Mode::Timer && self.at_zero == AtZero::Overtime && nanos < 0 {
+                        ui.label(format!("Current: +{}", format_nanos(nanos.saturating_neg())));
+                    } else {
+                        ui.label(format!("Current: {}", format_nanos(nanos)));
+                    }
+                }
+                if self.mode == Mode::Timer && self.at_zero == AtZero::Restart {
+                    ui.label(format!("Restarts left: {}", self.restarts_left.load(Ordering::Acquire)));
+                }
+                if self.adjustable() {
+                    self.quick_adjust_ui(ui);
+                }
             });
 
             ui.add_enabled_ui(self.counter.is_none(), |ui|{
                 ui.horizontal(|ui|{
                     ui.label(self.mode.get_desc());
-                    let mut s = self.time_s.load(Ordering::Acquire);
-                    egui::DragValue::new(&mut s)
-                        .custom_formatter(|mut sec,_|{
-                            let neg = sec.is_sign_negative();
-                            if neg {
-                                sec = -sec;
-                            }
-                            let s = sec.rem_euclid(SECONDS_IN_MINUTE as f64);
-                            let min = sec.div_euclid(SECONDS_IN_MINUTE as f64);
-                            let hr = min.div_euclid(MINUTES_IN_HOUR as f64);
-                            let min  = min.rem_euclid(MINUTES_IN_HOUR as f64);
-                            format!("{0}{hr:02.0}:{min:02.0}:{s:02.0}", if neg {"-"} else {""})
-                        }).custom_parser(|string|{
-                            let neg = string.strip_prefix("-");
-                            let vec = neg.unwrap_or(string).rsplit(":").collect::<Vec<_>>();
-                            let mut seconds = 0.;
-                            let mut conversion = 1.;
-                            for (loops, string) in vec.iter().enumerate() {
-                                seconds += conversion * f64::from_str(string).unwrap_or(0.);
-                                if loops < 3 {
-                                    //Hours->Minutes && Minutes->Seconds
-                                    conversion *= 60.;
-                                } else if loops == 4 {
-                                    //Days->Hours
-                                    conversion *= 24.;
-                                } else if loops == 4 {
-                                    //Months->Days
-                                    //365.2425 is the average Year length in Days of the Gregorian calendar
-                                    conversion *= 365.2425/12.;
-                                } else if loops == 5 {
-                                    //Year->Months
-                                    //365.2425 is the average Year length in Days of the Gregorian calendar
-                                    conversion *= 12.;
-                                } else {
-                                    //I don't care beyond this point. tbh everything past days is already extra
-                                    conversion *= 1000.;
+                    if self.mode == Mode::Deadline {
+                        self.deadline.ui(ui, &format!("deadline {}", self.id));
+                        return;
+                    }
+                    if self.mode == Mode::Tally {
+                        let mut count = self.initial_ns;
+                        if egui::DragValue::new(&mut count).ui(ui).changed() {
+                            self.set_start_value(count);
+                        }
+                        return;
+                    }
+                    let mut s = self.initial_ns as f64 / NANOS_IN_SECOND as f64;
+                    let response = egui::DragValue::new(&mut s)
+                        .custom_formatter(|sec, _| format_seconds(sec))
+                        .custom_parser(parse_seconds)
+                        .ui(ui);
+                    //Only write back on change, as the f64 can't represent every nanosecond.
+                    if response.changed() {
+                        self.set_start_value(seconds_to_nanos(s));
+                    }
+                })
+            });
+
+            ui.add_enabled_ui(self.counter.is_none(), |ui|{
+                ui.horizontal(|ui|{
+                    ui.label("Update every:");
+                    let mut ms = u64::try_from(self.period.as_millis()).unwrap_or(u64::MAX);
+                    egui::DragValue::new(&mut ms)
+                        .clamp_range(1..=SECONDS_IN_DAY * MILLIS_IN_SECOND as u64)
+                        .suffix(" ms")
+                        .ui(ui);
+                    self.period = Duration::from_millis(ms);
+                    if self.mode == Mode::Timer {
+                        ui.label("At zero:");
+                        egui::ComboBox::from_id_source((self.id, "at_zero"))
+                            .selected_text(self.at_zero.to_string())
+                            .show_ui(
+                                ui,
+                                |ui| for at_zero in AT_ZERO {
+                                    ui.selectable_value(&mut self.at_zero, *at_zero, at_zero.to_string());
                                 }
-                            }
-                            if neg.is_some() {
-                                seconds = -seconds;
-                            }
-                            Some(seconds)
-                        }).ui(ui);
-                    self.time_s.store(s, Ordering::Release);
+                            );
+                        if self.at_zero == AtZero::Restart && egui::DragValue::new(&mut self.restarts).suffix(" times").ui(ui).changed() {
+                            self.restarts_left.store(self.restarts, Ordering::Release);
+                        }
+                    }
                 })
-            })
+            });
+
+            ui.horizontal(|ui|{
+                ui.label("Format:").on_hover_text(format::TEMPLATE_HELP);
+                ui.add_enabled(self.counter.is_none(), egui::TextEdit::singleline(&mut self.format))
+                    .on_hover_text(format::TEMPLATE_HELP);
+                match Template::from_str(&self.format) {
+                    Ok(template) => {
+                        let nanos = self.time_ns.load(Ordering::Acquire);
+                        let overtime = self.mode == Mode::Timer && self.at_zero == AtZero::Overtime;
+                        ui.label(format!("Preview: {}", template.render(&Values{name: &self.name, nanos, overtime})));
+                    }
+                    Err(err) => {
+                        ui.colored_label(ui.visuals().error_fg_color, err.to_string());
+                    }
+                }
+            });
         }).response
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::counter_or_timer::output::{Destination, WriteMode};
+    use super::*;
+
+    fn tally(path: &Path) -> CounterTimer {
+        let mut tally = CounterTimer::new(CounterId(0), Arc::from("Deaths"), Default::default(), CommandSender::default());
+        tally.mode = Mode::Tally;
+        tally.format = format::TALLY_TEMPLATE.to_string();
+        tally.outputs = vec![OutputConfig {
+            destination: Destination::File { path: path.to_path_buf(), write_mode: WriteMode::Replace },
+            format: None,
+        }];
+        tally
+    }
+
+    fn temp_file(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("counter-{name}-{}.txt", std::process::id()))
+    }
+
+    fn timer(seconds: i64) -> CounterTimer {
+        let mut timer = CounterTimer::new(CounterId(0), Arc::from("Timer"), Default::default(), CommandSender::default());
+        timer.mode = Mode::Timer;
+        timer.outputs.clear();
+        //No tick in between, so only the stop decides the value
+        timer.period = Duration::from_secs(60);
+        timer.set_start_value(seconds * NANOS_IN_SECOND);
+        timer
+    }
+
+    ///Runs the counter for about `duration` and returns the time, it ran at most.
+    fn run_for(timer: &mut CounterTimer, duration: Duration) -> Duration {
+        let before = std::time::Instant::now();
+        timer.start_counter();
+        std::thread::sleep(duration);
+        timer.stop_counter();
+        before.elapsed()
+    }
+
+    ///Clocks of the ui and the task may differ a little.
+    const SLACK: i64 = 2 * NANOS_IN_MILLI;
+
+    #[test]
+    fn stop_keeps_exact_time() {
+        let _runtime = get_runtime().enter();
+        let mut timer = timer(10);
+        let mut min_run = Duration::ZERO;
+        let mut max_run = Duration::ZERO;
+        for duration in [Duration::from_millis(300), Duration::from_millis(200)] {
+            max_run += run_for(&mut timer, duration);
+            min_run += duration;
+            //Stored right away, without waiting for the task
+            let value = timer.time_ns.load(Ordering::Acquire);
+            let ran = 10 * NANOS_IN_SECOND - value;
+            assert!(ran >= min_run.as_nanos() as i64 - SLACK && ran <= max_run.as_nanos() as i64 + SLACK, "{ran} not in {min_run:?}..{max_run:?}");
+        }
+    }
+
+    ///A change right after stopping is not overwritten by the stopping task.
+    #[test]
+    fn change_right_after_stop() {
+        let _runtime = get_runtime().enter();
+        let mut timer = timer(10);
+        timer.period = Duration::from_millis(1);
+        run_for(&mut timer, Duration::from_millis(50));
+        timer.set_start_value(5 * NANOS_IN_SECOND);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(timer.time_ns.load(Ordering::Acquire), 5 * NANOS_IN_SECOND);
+        timer.wait_stopped();
+        assert_eq!(timer.time_ns.load(Ordering::Acquire), 5 * NANOS_IN_SECOND);
+    }
+
+    #[test]
+    fn tally_steps_write_without_starting() {
+        let _runtime = get_runtime().enter();
+        let path = temp_file("tally-stopped");
+        let mut tally = tally(&path);
+        tally.step_tally(1);
+        tally.step_tally(1);
+        tally.step_tally(-1);
+        get_runtime().block_on(tally.writing.take().unwrap()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "1");
+        assert!(!tally.is_running());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn running_tally_writes_steps_right_away() {
+        let _runtime = get_runtime().enter();
+        let path = temp_file("tally-running");
+        let mut tally = tally(&path);
+        tally.period = Duration::from_secs(60);
+        tally.start_counter();
+        tally.step_tally(5);
+        let written = get_runtime().block_on(async {
+            for _ in 0..50 {
+                if tokio::fs::read_to_string(&path).await.is_ok_and(|text| text == "5") {
+                    return true;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            false
+        });
+        assert!(written, "The step was not written before the next tick");
+        tally.stop_counter();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn restart_passes_zero_several_times() {
+        let initial = 10 * NANOS_IN_SECOND;
+        let restarts = AtomicU32::new(5);
+        let mut start = initial;
+        //Paused for 25 seconds past zero: three restarts bring it back to 5 seconds
+        assert_eq!(AtZero::Restart.apply(-25 * NANOS_IN_SECOND, &mut start, initial, &restarts), (5 * NANOS_IN_SECOND, false));
+        assert_eq!(start, 4 * initial);
+        assert_eq!(restarts.load(Ordering::Acquire), 2);
+        //Exactly zero restarts once
+        assert_eq!(AtZero::Restart.apply(0, &mut start, initial, &restarts), (initial, false));
+        assert_eq!(restarts.load(Ordering::Acquire), 1);
+        //Not enough restarts left: the remaining ones are used up and the Timer stops
+        assert_eq!(AtZero::Restart.apply(-15 * NANOS_IN_SECOND, &mut start, initial, &restarts), (0, true));
+        assert_eq!(start, 6 * initial);
+        assert_eq!(restarts.load(Ordering::Acquire), 0);
+        assert_eq!(AtZero::Restart.apply(-1, &mut start, initial, &restarts), (0, true));
+    }
+
+    #[test]
+    fn restart_without_start_value() {
+        let restarts = AtomicU32::new(3);
+        let mut start = 0;
+        assert_eq!(AtZero::Restart.apply(0, &mut start, 0, &restarts), (0, true));
+        assert_eq!(restarts.load(Ordering::Acquire), 3);
+        //The furthest a value can be past zero doesn't loop or overflow
+        assert_eq!(AtZero::Restart.apply(i64::MIN, &mut start, 1, &restarts), (0, true));
+        assert_eq!(restarts.load(Ordering::Acquire), 0);
+    }
+}
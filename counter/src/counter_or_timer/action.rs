@@ -0,0 +1,285 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use egui::{Ui, Widget};
+use serde::{Deserialize, Serialize};
+use crate::app::command::{CommandSender, Operation};
+use crate::app::popup::{handle_display_popup_arc, popup_creator, ArcPopupStore};
+use crate::counter_or_timer::{format_seconds, parse_seconds, seconds_to_nanos, Mode, NANOS_IN_SECOND};
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Trigger {
+    Start,
+    Stop,
+    ///The value reaches or passes zero
+    Zero,
+    ///The value reaches or passes the contained nanoseconds
+    Threshold(i64),
+}
+
+impl Trigger {
+    ///Whether going from `prev` to `current` reaches this trigger's value.
+    ///Always `false` for [`Trigger::Start`] and [`Trigger::Stop`].
+    pub fn reached(self, prev: i64, current: i64) -> bool {
+        let at = match self {
+            Self::Start | Self::Stop => return false,
+            Self::Zero => 0,
+            Self::Threshold(at) => at,
+        };
+        (prev < at && current >= at) || (prev > at && current <= at)
+    }
+    const fn env_name(self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Stop => "stop",
+            Self::Zero => "zero",
+            Self::Threshold(_) => "threshold",
+        }
+    }
+}
+
+impl Display for Trigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Start => write!(f, "On Start"),
+            Self::Stop => write!(f, "On Stop"),
+            Self::Zero => write!(f, "On reaching Zero"),
+            Self::Threshold(_) => write!(f, "On reaching"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Action {
+    ///Runs a shell command. Information about the counter is passed as `COUNTER_*` environment variables.
+    RunCommand(String),
+    ///Writes this text instead of the value, until the counter is stopped.
+    WriteText(String),
+    StartCounter(String),
+    StopCounter(String),
+    Popup(String),
+}
+
+impl Action {
+    const fn text(&self) -> &String {
+        match self {
+            Self::RunCommand(text)
+            | Self::WriteText(text)
+            | Self::StartCounter(text)
+            | Self::StopCounter(text)
+            | Self::Popup(text) => text,
+        }
+    }
+    fn text_mut(&mut self) -> &mut String {
+        match self {
+            Self::RunCommand(text)
+            | Self::WriteText(text)
+            | Self::StartCounter(text)
+            | Self::StopCounter(text)
+            | Self::Popup(text) => text,
+        }
+    }
+    fn kinds(text: String) -> [Self; 5] {
+        [
+            Self::RunCommand(text.clone()),
+            Self::WriteText(text.clone()),
+            Self::StartCounter(text.clone()),
+            Self::StopCounter(text.clone()),
+            Self::Popup(text),
+        ]
+    }
+    const fn hint(&self) -> &'static str {
+        match self {
+            Self::RunCommand(_) => "Shell command",
+            Self::WriteText(_) => "Text to write",
+            Self::StartCounter(_) | Self::StopCounter(_) => "Counter name",
+            Self::Popup(_) => "Message",
+        }
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RunCommand(_) => write!(f, "Run Command"),
+            Self::WriteText(_) => write!(f, "Write Text"),
+            Self::StartCounter(_) => write!(f, "Start Counter"),
+            Self::StopCounter(_) => write!(f, "Stop Counter"),
+            Self::Popup(_) => write!(f, "Show Popup"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct EventAction {
+    pub trigger: Trigger,
+    pub action: Action,
+}
+
+impl Default for EventAction {
+    fn default() -> Self {
+        Self {
+            trigger: Trigger::Zero,
+            action: Action::Popup(String::new()),
+        }
+    }
+}
+
+///Everything an [`Action`] might need to know about the counter that triggered it.
+pub struct ActionContext<'a> {
+    pub name: &'a Arc<str>,
+    pub mode: Mode,
+    pub nanos: i64,
+    pub text: &'a str,
+    pub popups: &'a ArcPopupStore,
+    pub commands: &'a CommandSender,
+}
+
+impl EventAction {
+    ///Runs the action. Returns the text to write instead of the value, if any.
+    pub fn run(&self, ctx: &ActionContext) -> Option<String> {
+        log::info!("Counter {}: {} triggered {}", ctx.name.as_ref(), self.trigger, self.action);
+        match &self.action {
+            Action::RunCommand(command) => run_command(command, self.trigger, ctx),
+            Action::WriteText(text) => return Some(text.clone()),
+            Action::StartCounter(name) => ctx.commands.send(Arc::from(name.as_str()), Operation::Start),
+            Action::StopCounter(name) => ctx.commands.send(Arc::from(name.as_str()), Operation::Stop),
+            Action::Popup(text) => {
+                let text = text.clone();
+                popup_creator(ctx.popups.clone(), ctx.name.to_string(), move |_, ui, _, _| {
+                    ui.label(&text);
+                });
+            }
+        }
+        None
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, id: impl std::hash::Hash + Copy) {
+        egui::ComboBox::from_id_source((id, "trigger"))
+            .selected_text(self.trigger.to_string())
+            .show_ui(ui, |ui| {
+                let threshold = match self.trigger {
+                    Trigger::Threshold(at) => at,
+                    _ => 0,
+                };
+                for trigger in [Trigger::Start, Trigger::Stop, Trigger::Zero, Trigger::Threshold(threshold)] {
+                    ui.selectable_value(&mut self.trigger, trigger, trigger.to_string());
+                }
+            });
+        if let Trigger::Threshold(at) = &mut self.trigger {
+            let mut s = *at as f64 / NANOS_IN_SECOND as f64;
+            if egui::DragValue::new(&mut s)
+                .custom_formatter(|sec, _| format_seconds(sec))
+                .custom_parser(parse_seconds)
+                .ui(ui)
+                .changed()
+            {
+                *at = seconds_to_nanos(s);
+            }
+        }
+        egui::ComboBox::from_id_source((id, "action"))
+            .selected_text(self.action.to_string())
+            .show_ui(ui, |ui| {
+                for action in Action::kinds(self.action.text().clone()) {
+                    let label = action.to_string();
+                    ui.selectable_value(&mut self.action, action, label);
+                }
+            });
+        let hint = self.action.hint();
+        ui.add(egui::TextEdit::singleline(self.action.text_mut()).hint_text(hint));
+    }
+}
+
+fn run_command(command: &str, trigger: Trigger, ctx: &ActionContext) {
+    let (shell, arg) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let child = tokio::process::Command::new(shell)
+        .arg(arg)
+        .arg(command)
+        .env("COUNTER_NAME", ctx.name.as_ref())
+        .env("COUNTER_MODE", ctx.mode.to_string())
+        .env("COUNTER_EVENT", trigger.env_name())
+        .env("COUNTER_NANOS", ctx.nanos.to_string())
+        .env("COUNTER_TEXT", ctx.text)
+        .kill_on_drop(false)
+        .spawn();
+    let popups = ctx.popups.clone();
+    let command = command.to_string();
+    match child {
+        Ok(mut child) => {
+            //Don't hold up the counter, whilst the command runs.
+            tokio::spawn(async move {
+                match child.wait().await {
+                    Ok(status) if status.success() => {}
+                    Ok(status) => {
+                        log::warn!("Command '{command}' exited with {status}");
+                        handle_display_popup_arc(&popups, format!("The command '{command}' failed"), &status, "Action failed");
+                    }
+                    Err(err) => {
+                        log::error!("Error waiting for command '{command}': {err}");
+                        handle_display_popup_arc(&popups, format!("The command '{command}' could not be awaited"), &err, "Action failed");
+                    }
+                }
+            });
+        }
+        Err(err) => {
+            log::error!("Error running command '{command}': {err}");
+            handle_display_popup_arc(&popups, format!("The command '{command}' could not be started"), &err, "Action failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AT: i64 = 30 * NANOS_IN_SECOND;
+
+    ///The trigger fires on the ticks, where the value goes from the previous to the next one.
+    fn fired(trigger: Trigger, values: &[i64]) -> Vec<bool> {
+        values.windows(2).map(|pair| trigger.reached(pair[0], pair[1])).collect()
+    }
+
+    #[test]
+    fn counting_up() {
+        let values = [28, 29, 30, 31].map(|s| s * NANOS_IN_SECOND);
+        assert_eq!(fired(Trigger::Threshold(AT), &values), [false, true, false]);
+    }
+
+    #[test]
+    fn counting_down() {
+        let values = [32, 31, 30, 29].map(|s| s * NANOS_IN_SECOND);
+        assert_eq!(fired(Trigger::Threshold(AT), &values), [false, true, false]);
+    }
+
+    ///Landing exactly on the value fires once, not again when leaving it.
+    #[test]
+    fn exact_hit() {
+        assert!(Trigger::Threshold(AT).reached(AT - 1, AT));
+        assert!(!Trigger::Threshold(AT).reached(AT, AT + 1));
+        assert!(!Trigger::Threshold(AT).reached(AT, AT - 1));
+        assert!(!Trigger::Threshold(AT).reached(AT, AT));
+    }
+
+    ///A long tick or an added amount of time can jump over the value.
+    #[test]
+    fn skipped_over() {
+        assert!(Trigger::Threshold(AT).reached(AT - NANOS_IN_SECOND, AT + 5 * NANOS_IN_SECOND));
+        assert!(Trigger::Threshold(AT).reached(AT + 5 * NANOS_IN_SECOND, AT - NANOS_IN_SECOND));
+        assert!(!Trigger::Threshold(AT).reached(AT + NANOS_IN_SECOND, AT + 5 * NANOS_IN_SECOND));
+    }
+
+    #[test]
+    fn zero() {
+        //Overtime keeps counting past zero, which only fires once
+        let overtime = [2, 1, 0, -1, -2].map(|s| s * NANOS_IN_SECOND);
+        assert_eq!(fired(Trigger::Zero, &overtime), [false, true, false, false]);
+        //A restart sees the raw value past zero, and then continues from the restored value
+        assert!(Trigger::Zero.reached(NANOS_IN_SECOND, -NANOS_IN_SECOND));
+        assert!(!Trigger::Zero.reached(9 * NANOS_IN_SECOND, 8 * NANOS_IN_SECOND));
+    }
+
+    #[test]
+    fn start_and_stop_never_reached() {
+        assert!(!Trigger::Start.reached(-1, 1));
+        assert!(!Trigger::Stop.reached(1, -1));
+    }
+}
@@ -0,0 +1,230 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use chrono::{DateTime, Utc};
+use egui::{Ui, Widget};
+use tokio::time::Instant;
+use crate::counter_or_timer::{format_seconds, parse_seconds, seconds_to_nanos, CounterTimer, Mode, RunningSince, MILLIS_IN_SECOND, MINUTES_IN_HOUR, NANOS_IN_MILLI, NANOS_IN_SECOND, SECONDS_IN_MINUTE};
+
+///Messages from the ui to a running counter task.
+#[derive(Copy, Clone, Debug)]
+pub(super) enum TaskMsg {
+    ///Stop. The ui stored the final value already.
+    Stop,
+    ///Add nanoseconds to the value
+    Add(i64),
+    ///Make the value at the instant the contained nanoseconds
+    Set(i64, Instant),
+    ///Write the value now instead of on the next tick, because the count of a [`Mode::Tally`] changed
+    Refresh,
+}
+
+pub(super) fn default_adjustments() -> Vec<i64> {
+    vec![60 * NANOS_IN_SECOND, 5 * 60 * NANOS_IN_SECOND]
+}
+
+///Formats an amount of time as short as possible, e.g. `5:00` or `1:00:00.5`.
+fn format_amount(ns: i64) -> String {
+    let millis = ns.unsigned_abs() / NANOS_IN_MILLI as u64;
+    let ms = millis % MILLIS_IN_SECOND as u64;
+    let s = millis / MILLIS_IN_SECOND as u64;
+    let min = s / SECONDS_IN_MINUTE;
+    let s = s % SECONDS_IN_MINUTE;
+    let hr = min / MINUTES_IN_HOUR;
+    let min = min % MINUTES_IN_HOUR;
+    let mut out = if hr > 0 {
+        format!("{hr}:{min:02}:{s:02}")
+    } else {
+        format!("{min}:{s:02}")
+    };
+    if ms > 0 {
+        out.push_str(format!(".{ms:03}").trim_end_matches('0'));
+    }
+    out
+}
+
+impl CounterTimer {
+    ///Whether the value can be changed, while the counter is running.
+    pub const fn adjustable(&self) -> bool {
+        matches!(self.mode, Mode::Counter | Mode::Timer)
+    }
+    ///Whether the value is a time. A [`Mode::Tally`] counts instead.
+    pub const fn is_timed(&self) -> bool {
+        !matches!(self.mode, Mode::Tally)
+    }
+    ///Adds to the current value. A running counter changes on its next tick.
+    ///Returns `false`, if the counter is running and not [`CounterTimer::adjustable`].
+    pub fn add_time(&mut self, nanos: i64) -> bool {
+        self.check_counter();
+        match &self.counter {
+            None => {
+                let _ = self.time_ns.fetch_update(Ordering::AcqRel, Ordering::Acquire, |time| Some(time.saturating_add(nanos)));
+            }
+            Some(_) if !self.adjustable() => return false,
+            Some((sender, _)) => {
+                let _ = sender.send(TaskMsg::Add(nanos));
+                if let Some(running) = &mut self.running {
+                    running.start_ns = running.start_ns.saturating_add(nanos);
+                }
+            }
+        }
+        true
+    }
+    ///Adds to the current value, but doesn't push it above `max`.
+    ///Returns the nanoseconds added, or `None`, if the counter is running and not [`CounterTimer::adjustable`].
+    pub fn add_time_capped(&mut self, nanos: i64, max: i64) -> Option<i64> {
+        self.check_counter();
+        let room = max.saturating_sub(self.current_ns()).max(0);
+        let nanos = nanos.min(room);
+        self.add_time(nanos).then_some(nanos)
+    }
+    ///The value right now. For a running counter this includes adds, that its task hasn't applied yet.
+    fn current_ns(&self) -> i64 {
+        let (Some(_), Some(running), true) = (&self.counter, &self.running, self.adjustable()) else {
+            return self.time_ns.load(Ordering::Acquire);
+        };
+        self.running_ns(running, None).0
+    }
+    ///The value of a running counter right now, before [`AtZero`](crate::counter_or_timer::AtZero) is applied,
+    ///and the start it is counted from, as the task computes them.
+    pub(super) fn running_ns(&self, running: &RunningSince, deadline: Option<DateTime<Utc>>) -> (i64, i64) {
+        //Every restart the task did moved its start by the start value
+        let restarts = running.restarts_left.saturating_sub(self.restarts_left.load(Ordering::Acquire));
+        let start_ns = running.start_ns.saturating_add(self.initial_ns.saturating_mul(i64::from(restarts)));
+        let elapsed = (Utc::now() - running.since).to_std().unwrap_or_default();
+        (self.mode.get_timestamp(&Arc::default(), start_ns, elapsed, deadline).0, start_ns)
+    }
+    ///Sets the current value. A running counter changes on its next tick.
+    ///A stopped counter also uses the value as its start value.
+    ///Returns `false`, if the counter is running and not [`CounterTimer::adjustable`].
+    pub fn set_time(&mut self, nanos: i64) -> bool {
+        self.check_counter();
+        match &self.counter {
+            None => self.set_start_value(nanos),
+            Some(_) if !self.adjustable() => return false,
+            Some((sender, _)) => {
+                let _ = sender.send(TaskMsg::Set(nanos, Instant::now()));
+                //Resuming continues from the new value
+                self.running = Some(RunningSince{
+                    since: Utc::now(),
+                    start_ns: nanos,
+                    restarts_left: self.restarts_left.load(Ordering::Acquire),
+                });
+            }
+        }
+        true
+    }
+
+    ///A button to subtract and to add every configured amount.
+    pub(super) fn quick_adjust_ui(&mut self, ui: &mut Ui) {
+        for amount in self.adjustments.clone() {
+            if ui.button(format!("-{}", format_amount(amount))).clicked() {
+                self.add_time(amount.saturating_neg());
+            }
+            if ui.button(format!("+{}", format_amount(amount))).clicked() {
+                self.add_time(amount);
+            }
+        }
+        if ui.button("Adjust…").clicked() {
+            self.adjust_open = !self.adjust_open;
+        }
+    }
+
+    pub(super) fn adjust_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.adjust_open;
+        egui::Window::new(format!("Adjust {}", self.name))
+            .id(egui::Id::new((self.id, "adjust")))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label("Changes take effect on the next update, without stopping the counter.");
+                ui.horizontal(|ui| {
+                    let mut s = self.adjust_ns as f64 / NANOS_IN_SECOND as f64;
+                    if egui::DragValue::new(&mut s)
+                        .custom_formatter(|sec, _| format_seconds(sec))
+                        .custom_parser(parse_seconds)
+                        .ui(ui)
+                        .changed()
+                    {
+                        self.adjust_ns = seconds_to_nanos(s);
+                    }
+                    if ui.button("Add").clicked() {
+                        self.add_time(self.adjust_ns);
+                    }
+                    if ui.button("Subtract").clicked() {
+                        self.add_time(self.adjust_ns.saturating_neg());
+                    }
+                    if ui.button("Set to").clicked() {
+                        self.set_time(self.adjust_ns);
+                    }
+                });
+                ui.separator();
+                ui.label("Amounts of the quick buttons:");
+                let mut remove = None;
+                for (index, amount) in self.adjustments.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        let mut s = *amount as f64 / NANOS_IN_SECOND as f64;
+                        if egui::DragValue::new(&mut s)
+                            .custom_formatter(|sec, _| format_seconds(sec))
+                            .custom_parser(parse_seconds)
+                            .clamp_range(0.0..=f64::MAX)
+                            .ui(ui)
+                            .changed()
+                        {
+                            *amount = seconds_to_nanos(s);
+                        }
+                        if ui.button("Delete").clicked() {
+                            remove = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = remove {
+                    self.adjustments.remove(index);
+                }
+                if ui.button("Add Amount").clicked() {
+                    self.adjustments.push(60 * NANOS_IN_SECOND);
+                }
+            });
+        self.adjust_open = open;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::command::CommandSender;
+    use crate::counter_or_timer::CounterId;
+    use crate::get_runtime;
+    use super::*;
+
+    fn timer(seconds: i64) -> CounterTimer {
+        let mut timer = CounterTimer::new(CounterId(0), Arc::from("Timer"), Default::default(), CommandSender::default());
+        timer.mode = Mode::Timer;
+        timer.outputs.clear();
+        timer.set_start_value(seconds * NANOS_IN_SECOND);
+        timer
+    }
+
+    #[test]
+    fn capped_stopped() {
+        let mut timer = timer(50);
+        let max = 60 * NANOS_IN_SECOND;
+        let added: Vec<_> = (0..4).map(|_| timer.add_time_capped(4 * NANOS_IN_SECOND, max)).collect();
+        assert_eq!(added, [Some(4 * NANOS_IN_SECOND), Some(4 * NANOS_IN_SECOND), Some(2 * NANOS_IN_SECOND), Some(0)]);
+        assert_eq!(timer.time_ns.load(Ordering::Acquire), max);
+        //Removing time isn't limited
+        assert_eq!(timer.add_time_capped(-10 * NANOS_IN_SECOND, max), Some(-10 * NANOS_IN_SECOND));
+    }
+
+    ///Adds, that the running task hasn't applied yet, count against the maximum.
+    #[test]
+    fn capped_running() {
+        let _runtime = get_runtime().enter();
+        let mut timer = timer(50);
+        timer.start_counter();
+        let max = 60 * NANOS_IN_SECOND;
+        let added: i64 = (0..5).map(|_| timer.add_time_capped(4 * NANOS_IN_SECOND, max).unwrap()).sum();
+        //The Timer keeps counting down in between, which makes a little room
+        assert!((10 * NANOS_IN_SECOND..11 * NANOS_IN_SECOND).contains(&added), "{added}");
+        assert!(timer.current_ns() <= max);
+        timer.stop_counter();
+        assert!(timer.time_ns.load(Ordering::Acquire) <= max);
+    }
+}
@@ -0,0 +1,125 @@
+use chrono::{DateTime, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use egui::Ui;
+use serde::{Deserialize, Serialize};
+
+const LOCAL_TZ_NAME: &str = "Local";
+
+///An absolute point in time, entered as a date and a time in a time zone.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+pub struct Deadline {
+    date: NaiveDate,
+    time: NaiveTime,
+    ///`None` means the system's local time zone.
+    tz: Option<Tz>,
+}
+
+impl Default for Deadline {
+    fn default() -> Self {
+        Self {
+            date: chrono::Local::now().date_naive(),
+            time: NaiveTime::MIN,
+            tz: None,
+        }
+    }
+}
+
+impl Deadline {
+    fn tz_name(&self) -> &'static str {
+        self.tz.map_or(LOCAL_TZ_NAME, |tz| tz.name())
+    }
+
+    ///Converts the entered date and time to UTC.
+    ///If the time exists twice (e.g. when the clocks are turned back), the earlier one is used.
+    ///Returns `None` if the time doesn't exist in the time zone (e.g. when the clocks are turned forward).
+    pub fn resolve(&self) -> Option<DateTime<Utc>> {
+        let naive = NaiveDateTime::new(self.date, self.time);
+        fn to_utc<T: TimeZone>(result: LocalResult<DateTime<T>>) -> Option<DateTime<Utc>> {
+            result.earliest().map(|time| time.with_timezone(&Utc))
+        }
+        match self.tz {
+            None => to_utc(chrono::Local.from_local_datetime(&naive)),
+            Some(tz) => to_utc(tz.from_local_datetime(&naive)),
+        }
+    }
+
+    ///Adds the date, time and time zone pickers to an existing (horizontal) layout.
+    pub fn ui(&mut self, ui: &mut Ui, id: &str) {
+        ui.add(egui_extras::DatePickerButton::new(&mut self.date).id_source(id));
+
+        let mut h = self.time.hour();
+        let mut m = self.time.minute();
+        let mut s = self.time.second();
+        ui.add(egui::DragValue::new(&mut h).clamp_range(0..=23).custom_formatter(|v, _| format!("{v:02.0}")));
+        ui.label(":");
+        ui.add(egui::DragValue::new(&mut m).clamp_range(0..=59).custom_formatter(|v, _| format!("{v:02.0}")));
+        ui.label(":");
+        ui.add(egui::DragValue::new(&mut s).clamp_range(0..=59).custom_formatter(|v, _| format!("{v:02.0}")));
+        if let Some(time) = NaiveTime::from_hms_opt(h, m, s) {
+            self.time = time;
+        }
+
+        egui::ComboBox::from_id_source((id, "tz"))
+            .selected_text(self.tz_name())
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.tz, None, LOCAL_TZ_NAME);
+                for tz in chrono_tz::TZ_VARIANTS {
+                    ui.selectable_value(&mut self.tz, Some(tz), tz.name());
+                }
+            });
+
+        if self.resolve().is_none() {
+            ui.colored_label(ui.visuals().error_fg_color, "This time does not exist in the selected time zone");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use crate::counter_or_timer::{Mode, NANOS_IN_SECOND};
+    use super::*;
+
+    fn deadline(date: (i32, u32, u32), time: (u32, u32), tz: Tz) -> Deadline {
+        Deadline {
+            date: NaiveDate::from_ymd_opt(date.0, date.1, date.2).unwrap(),
+            time: NaiveTime::from_hms_opt(time.0, time.1, 0).unwrap(),
+            tz: Some(tz),
+        }
+    }
+
+    fn utc(date: (i32, u32, u32), time: (u32, u32)) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(date.0, date.1, date.2, time.0, time.1, 0).unwrap()
+    }
+
+    #[test]
+    fn plain_time() {
+        //Central European Summer Time is two hours ahead
+        assert_eq!(deadline((2024, 6, 1), (12, 0), Tz::Europe__Berlin).resolve(), Some(utc((2024, 6, 1), (10, 0))));
+        assert_eq!(deadline((2024, 6, 1), (12, 0), Tz::UTC).resolve(), Some(utc((2024, 6, 1), (12, 0))));
+    }
+
+    ///On 2024-03-31 Berlin skips from 02:00 to 03:00.
+    #[test]
+    fn gap() {
+        assert_eq!(deadline((2024, 3, 31), (2, 30), Tz::Europe__Berlin).resolve(), None);
+        assert_eq!(deadline((2024, 3, 31), (3, 0), Tz::Europe__Berlin).resolve(), Some(utc((2024, 3, 31), (1, 0))));
+    }
+
+    ///On 2024-10-27 Berlin goes through 02:00 to 03:00 twice. The earlier one, still in summer time, is used.
+    #[test]
+    fn fold() {
+        assert_eq!(deadline((2024, 10, 27), (2, 30), Tz::Europe__Berlin).resolve(), Some(utc((2024, 10, 27), (0, 30))));
+    }
+
+    #[test]
+    fn past_deadline() {
+        let past = deadline((2000, 1, 1), (0, 0), Tz::UTC).resolve();
+        assert_eq!(past, Some(utc((2000, 1, 1), (0, 0))));
+        //Counts on into negative values
+        let (nanos, maxed) = Mode::Deadline.get_timestamp(&Arc::default(), 0, Duration::ZERO, past);
+        assert!(!maxed);
+        assert!(nanos < -20 * 365 * 24 * 3600 * NANOS_IN_SECOND, "{nanos}");
+    }
+}
@@ -0,0 +1,310 @@
+use std::fmt::{Display, Formatter, Write};
+use std::str::FromStr;
+use crate::counter_or_timer::{HOURS_IN_DAY, MILLIS_IN_SECOND, MINUTES_IN_HOUR, NANOS_IN_MILLI, SECONDS_IN_MINUTE};
+
+pub const DEFAULT_TEMPLATE: &str = "{sign}{H:2}:{m:2}:{s:2}";
+///Used instead of [`DEFAULT_TEMPLATE`] by a Tally.
+pub const TALLY_TEMPLATE: &str = "{n}";
+
+///Short description of every token, shown as a hover text next to the template editor.
+pub const TEMPLATE_HELP: &str = "Text outside of braces is copied as-is. Use {{ and }} for literal braces.
+Tokens:
+{D} days, {h} hours (0-23), {H} total hours,
+{m} minutes (0-59), {M} total minutes,
+{s} seconds (0-59), {S} total seconds,
+{ms} milliseconds (000-999), {cs} hundredths (00-99), {ds} tenths (0-9),
+{sign} '-' if negative or '+' in overtime, {name} the counter name,
+{n} the count of a Tally.
+Numbers can be zero padded to a width, e.g. {H:2} or {M:3}.";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Field {
+    Days,
+    Hours,
+    TotalHours,
+    Minutes,
+    TotalMinutes,
+    Seconds,
+    TotalSeconds,
+    Millis,
+    Centis,
+    Decis,
+    Sign,
+    Name,
+    Count,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "D" => Self::Days,
+            "h" => Self::Hours,
+            "H" => Self::TotalHours,
+            "m" => Self::Minutes,
+            "M" => Self::TotalMinutes,
+            "s" => Self::Seconds,
+            "S" => Self::TotalSeconds,
+            "ms" => Self::Millis,
+            "cs" => Self::Centis,
+            "ds" => Self::Decis,
+            "sign" => Self::Sign,
+            "name" => Self::Name,
+            "n" => Self::Count,
+            _ => return None,
+        })
+    }
+    const fn is_numeric(self) -> bool {
+        !matches!(self, Self::Sign | Self::Name)
+    }
+    const fn default_width(self) -> usize {
+        match self {
+            Self::Millis => 3,
+            Self::Centis => 2,
+            Self::Decis => 1,
+            _ => 0,
+        }
+    }
+}
+
+///A piece of a parsed template. `F` is what a `{…}` placeholder was parsed into.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Token<F> {
+    Literal(String),
+    Field(F),
+}
+
+///Splits `s` into literal text and `{…}` placeholders, which `field` parses.
+///`{{` and `}}` are literal braces.
+pub(crate) fn parse_tokens<F>(s: &str, mut field: impl FnMut(String) -> Result<F, TemplateError>) -> Result<Vec<Token<F>>, TemplateError> {
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '{' if chars.next_if(|(_, c)| *c == '{').is_some() => literal.push('{'),
+            '}' if chars.next_if(|(_, c)| *c == '}').is_some() => literal.push('}'),
+            '}' => return Err(TemplateError::UnmatchedClose(pos)),
+            '{' => {
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '}')) => break,
+                        Some((_, c)) => inner.push(c),
+                        None => return Err(TemplateError::UnclosedBrace(pos)),
+                    }
+                }
+                let parsed = field(inner)?;
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(core::mem::take(&mut literal)));
+                }
+                tokens.push(Token::Field(parsed));
+            }
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TemplateError {
+    UnclosedBrace(usize),
+    UnmatchedClose(usize),
+    UnknownToken(String),
+    InvalidWidth(String),
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnclosedBrace(pos) => write!(f, "The '{{' at position {pos} is never closed. Use '{{{{' for a literal brace."),
+            Self::UnmatchedClose(pos) => write!(f, "The '}}' at position {pos} has no matching '{{'. Use '}}}}' for a literal brace."),
+            Self::UnknownToken(token) => write!(f, "Unknown token '{{{token}}}'"),
+            Self::InvalidWidth(token) => write!(f, "Invalid width in '{{{token}}}'. Widths are only allowed on numbers, e.g. {{H:2}}"),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+///A parsed output format. Parse it once with [`FromStr`] and render it every tick.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Template {
+    tokens: Vec<Token<(Field, usize)>>,
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Self::from_str(DEFAULT_TEMPLATE).expect("The default template should always parse")
+    }
+}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = parse_tokens(s, |inner| {
+            let (name, width) = match inner.split_once(':') {
+                Some((name, width)) => (name, Some(width)),
+                None => (inner.as_str(), None),
+            };
+            let field = Field::from_name(name).ok_or_else(|| TemplateError::UnknownToken(inner.clone()))?;
+            let width = match width {
+                None => field.default_width(),
+                Some(width) if field.is_numeric() => usize::from_str(width).map_err(|_| TemplateError::InvalidWidth(inner.clone()))?,
+                Some(_) => return Err(TemplateError::InvalidWidth(inner.clone())),
+            };
+            Ok((field, width))
+        })?;
+        Ok(Self { tokens })
+    }
+}
+
+///Everything a [`Template`] can reference.
+pub struct Values<'a> {
+    pub name: &'a str,
+    pub nanos: i64,
+    ///Negative values are shown with a '+' sign instead.
+    pub overtime: bool,
+}
+
+impl Template {
+    pub fn render(&self, values: &Values) -> String {
+        let neg = values.nanos.is_negative();
+        let millis = values.nanos.unsigned_abs() / NANOS_IN_MILLI.unsigned_abs();
+        let total_s = millis / MILLIS_IN_SECOND.unsigned_abs();
+        let total_m = total_s / SECONDS_IN_MINUTE;
+        let total_h = total_m / MINUTES_IN_HOUR;
+        let mut out = String::new();
+        for token in &self.tokens {
+            let (field, width) = match token {
+                Token::Literal(literal) => {
+                    out.push_str(literal);
+                    continue;
+                }
+                Token::Field((field, width)) => (*field, *width),
+            };
+            let number = match field {
+                Field::Sign => {
+                    if neg {
+                        out.push(if values.overtime { '+' } else { '-' });
+                    }
+                    continue;
+                }
+                Field::Name => {
+                    out.push_str(values.name);
+                    continue;
+                }
+                Field::Count => {
+                    push_padded(&mut out, values.nanos, width);
+                    continue;
+                }
+                Field::Days => total_h / HOURS_IN_DAY,
+                Field::Hours => total_h % HOURS_IN_DAY,
+                Field::TotalHours => total_h,
+                Field::Minutes => total_m % MINUTES_IN_HOUR,
+                Field::TotalMinutes => total_m,
+                Field::Seconds => total_s % SECONDS_IN_MINUTE,
+                Field::TotalSeconds => total_s,
+                Field::Millis => millis % MILLIS_IN_SECOND.unsigned_abs(),
+                Field::Centis => millis % MILLIS_IN_SECOND.unsigned_abs() / 10,
+                Field::Decis => millis % MILLIS_IN_SECOND.unsigned_abs() / 100,
+            };
+            push_padded(&mut out, number, width);
+        }
+        out
+    }
+}
+
+///Appends the number zero padded to `width`.
+fn push_padded(out: &mut String, number: impl Display, width: usize) {
+    //Writing to a String can't fail
+    let _ = write!(out, "{number:0width$}");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::counter_or_timer::NANOS_IN_SECOND as SECOND;
+    use super::*;
+
+    fn render(template: &str, nanos: i64) -> String {
+        render_with(template, nanos, false)
+    }
+
+    fn render_with(template: &str, nanos: i64, overtime: bool) -> String {
+        Template::from_str(template).unwrap().render(&Values { name: "Break", nanos, overtime })
+    }
+
+    #[test]
+    fn tokens() {
+        //1 day, 2 hours, 3 minutes, 4 seconds and 567 milliseconds
+        let nanos = ((((24 + 2) * 60 + 3) * 60 + 4) * 1000 + 567) * 1_000_000;
+        assert_eq!(render("{D}", nanos), "1");
+        assert_eq!(render("{h}", nanos), "2");
+        assert_eq!(render("{H}", nanos), "26");
+        assert_eq!(render("{m}", nanos), "3");
+        assert_eq!(render("{M}", nanos), "1563");
+        assert_eq!(render("{s}", nanos), "4");
+        assert_eq!(render("{S}", nanos), "93784");
+        assert_eq!(render("{ms}", nanos), "567");
+        assert_eq!(render("{cs}", nanos), "56");
+        assert_eq!(render("{ds}", nanos), "5");
+        assert_eq!(render("{name}", nanos), "Break");
+        assert_eq!(render("{n}", 42), "42");
+        assert_eq!(render(DEFAULT_TEMPLATE, nanos), "26:03:04");
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(render("{H:2}:{m:2}:{s:2}", 65 * SECOND), "00:01:05");
+        assert_eq!(render("{M:3}", 65 * SECOND), "001");
+        //Fractions are padded by default
+        assert_eq!(render("{s}.{ms}|{cs}|{ds}", 5 * SECOND + 7_000_000), "5.007|00|0");
+        assert_eq!(render("{ms:1}", 7_000_000), "7");
+        assert_eq!(render("{n:3}", 7), "007");
+        //Too long numbers are not cut off
+        assert_eq!(render("{s:1}", 42 * SECOND), "42");
+    }
+
+    #[test]
+    fn sign() {
+        assert_eq!(render("{sign}{s}", -5 * SECOND), "-5");
+        assert_eq!(render("{sign}{s}", 5 * SECOND), "5");
+        assert_eq!(render_with("{sign}{s}", -5 * SECOND, true), "+5");
+        assert_eq!(render_with("{sign}{s}", 5 * SECOND, true), "5");
+        assert_eq!(render("{n}", -3), "-3");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(render("{{{s}}} left", 5 * SECOND), "{5} left");
+        assert_eq!(render("{{s}}", 5 * SECOND), "{s}");
+        assert_eq!(render("", 5 * SECOND), "");
+        assert_eq!(render("plain text", 5 * SECOND), "plain text");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Template::from_str("{s"), Err(TemplateError::UnclosedBrace(0)));
+        assert_eq!(Template::from_str("ab}"), Err(TemplateError::UnmatchedClose(2)));
+        assert_eq!(Template::from_str("{x}"), Err(TemplateError::UnknownToken("x".to_string())));
+        assert_eq!(Template::from_str("{}"), Err(TemplateError::UnknownToken(String::new())));
+        assert_eq!(Template::from_str("{s:x}"), Err(TemplateError::InvalidWidth("s:x".to_string())));
+        assert_eq!(Template::from_str("{name:2}"), Err(TemplateError::InvalidWidth("name:2".to_string())));
+        assert_eq!(Template::from_str("{sign:1}"), Err(TemplateError::InvalidWidth("sign:1".to_string())));
+        assert_eq!(Template::from_str("{H:2}:{m:2"), Err(TemplateError::UnclosedBrace(6)));
+    }
+
+    #[test]
+    fn other_fields() {
+        let names = |s: &str| parse_tokens(s, Ok);
+        assert_eq!(names("a {Break} b {{c}}"), Ok(vec![
+            Token::Literal("a ".to_string()),
+            Token::Field("Break".to_string()),
+            Token::Literal(" b {c}".to_string()),
+        ]));
+        assert_eq!(names("}"), Err(TemplateError::UnmatchedClose(0)));
+    }
+}
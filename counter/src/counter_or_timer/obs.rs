@@ -0,0 +1,345 @@
+use std::sync::Arc;
+use std::time::Duration;
+use base64::Engine;
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::watch;
+use tokio_tungstenite::tungstenite::Message;
+use crate::app::popup::{handle_display_popup_arc, ArcPopupStore};
+
+const DEFAULT_PORT: u16 = 4455;
+const RPC_VERSION: u64 = 1;
+const MIN_RETRY: Duration = Duration::from_secs(1);
+const MAX_RETRY: Duration = Duration::from_secs(30);
+
+///Where to show the counter in OBS, using obs-websocket 5.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(default)]
+pub(crate) struct ObsConfig {
+    pub host: String,
+    pub port: u16,
+    ///Empty, if authentication is disabled in OBS
+    pub password: String,
+    ///Name of the text source, that gets the value
+    pub source: String,
+}
+
+impl Default for ObsConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: DEFAULT_PORT,
+            password: String::new(),
+            source: String::new(),
+        }
+    }
+}
+
+impl ObsConfig {
+    pub fn ui(&mut self, ui: &mut egui::Ui, id: impl std::hash::Hash) {
+        egui::Grid::new(id).num_columns(2).show(ui, |ui|{
+            ui.label("Host:");
+            ui.horizontal(|ui|{
+                ui.text_edit_singleline(&mut self.host);
+                ui.add(egui::DragValue::new(&mut self.port));
+            });
+            ui.end_row();
+            ui.label("Password:");
+            ui.add(egui::TextEdit::singleline(&mut self.password).password(true).hint_text("none"));
+            ui.end_row();
+            ui.label("Source:");
+            ui.add(egui::TextEdit::singleline(&mut self.source).hint_text("name of a text source"));
+            ui.end_row();
+        });
+        ui.label("Enable the WebSocket Server in OBS under Tools → WebSocket Server Settings.");
+    }
+}
+
+type Socket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+///Sends the latest text to OBS in the background, reconnecting as needed.
+///Once dropped, the last text is still delivered if connected, then the connection closes.
+pub(super) struct ObsSink {
+    text: watch::Sender<String>,
+    ///The reason, the last connection attempt failed
+    error: watch::Receiver<Option<String>>,
+}
+
+impl ObsSink {
+    pub fn start(config: ObsConfig, name: Arc<str>, popups: ArcPopupStore) -> Self {
+        let (text, recv) = watch::channel(String::new());
+        let (error_send, error) = watch::channel(None);
+        tokio::spawn(run(config, name, recv, error_send, popups));
+        Self { text, error }
+    }
+    pub fn error(&self) -> Option<String> {
+        self.error.borrow().clone()
+    }
+    pub fn set_text(&self, text: &str) {
+        self.text.send_if_modified(|current| {
+            if current == text {
+                return false;
+            }
+            text.clone_into(current);
+            true
+        });
+    }
+}
+
+async fn run(config: ObsConfig, name: Arc<str>, mut text: watch::Receiver<String>, error: watch::Sender<Option<String>>, popups: ArcPopupStore) {
+    let addr = format!("ws://{}:{}", config.host, config.port);
+    let mut backoff = MIN_RETRY;
+    //Only the first error of a streak gets a popup
+    let mut reported = false;
+    loop {
+        let err = match connect(&addr, &config.password).await {
+            Ok(mut socket) => {
+                log::info!("Counter {name} connected to OBS at {addr}");
+                backoff = MIN_RETRY;
+                reported = false;
+                error.send_replace(None);
+                //Send the current text right away
+                text.mark_changed();
+                match forward(&mut socket, &config.source, &mut text).await {
+                    Ok(()) => {
+                        let _ = socket.close(None).await;
+                        return;
+                    }
+                    Err(err) => err,
+                }
+            }
+            Err(err) => err,
+        };
+        log::warn!("Counter {name} lost the connection to OBS at {addr}: {err}");
+        error.send_replace(Some(err.to_string()));
+        if !reported {
+            reported = true;
+            handle_display_popup_arc(
+                &popups,
+                format!("Counter {name} could not update OBS at {addr}. Retrying in the background."),
+                &err,
+                "OBS connection failed"
+            );
+        }
+        let retry = tokio::time::sleep(backoff);
+        tokio::pin!(retry);
+        loop {
+            tokio::select! {
+                _ = &mut retry => break,
+                //Fails, once the counter stopped
+                changed = text.changed() => if changed.is_err() {
+                    return;
+                },
+            }
+        }
+        backoff = (backoff * 2).min(MAX_RETRY);
+    }
+}
+
+///Connects and authenticates.
+async fn connect(addr: &str, password: &str) -> Result<Socket, Error> {
+    let (mut socket, _) = tokio_tungstenite::connect_async(addr).await?;
+    let hello = receive(&mut socket).await?;
+    if hello["op"] != 0 {
+        return Err(format!("Expected Hello from OBS, got {hello}").into());
+    }
+    let mut identify = json!({"rpcVersion": RPC_VERSION, "eventSubscriptions": 0});
+    if let Some(auth) = hello["d"].get("authentication") {
+        let (Some(challenge), Some(salt)) = (auth["challenge"].as_str(), auth["salt"].as_str()) else {
+            return Err("OBS sent an invalid authentication challenge".into());
+        };
+        identify["authentication"] = Value::String(authentication(password, salt, challenge));
+    }
+    socket.send(Message::Text(json!({"op": 1, "d": identify}).to_string())).await?;
+    let identified = receive(&mut socket).await?;
+    if identified["op"] != 2 {
+        return Err(format!("OBS did not accept the connection. Is the password correct? Got {identified}").into());
+    }
+    Ok(socket)
+}
+
+///`base64(sha256(base64(sha256(password + salt)) + challenge))`
+fn authentication(password: &str, salt: &str, challenge: &str) -> String {
+    let base64 = base64::engine::general_purpose::STANDARD;
+    let secret = base64.encode(Sha256::digest(format!("{password}{salt}")));
+    base64.encode(Sha256::digest(format!("{secret}{challenge}")))
+}
+
+///The next json message. A closed connection is an error.
+async fn receive(socket: &mut Socket) -> Result<Value, Error> {
+    loop {
+        match socket.next().await {
+            Some(Ok(Message::Text(text))) => return Ok(serde_json::from_str(&text)?),
+            Some(Ok(Message::Close(frame))) => return Err(format!("OBS closed the connection: {frame:?}").into()),
+            //Pings are answered by tungstenite
+            Some(Ok(_)) => {}
+            Some(Err(err)) => return Err(err.into()),
+            None => return Err("OBS closed the connection".into()),
+        }
+    }
+}
+
+///Sets the source to every new text. Returns `Ok`, once the counter stopped.
+async fn forward(socket: &mut Socket, source: &str, text: &mut watch::Receiver<String>) -> Result<(), Error> {
+    let mut request_id = 0u64;
+    loop {
+        tokio::select! {
+            changed = text.changed() => {
+                //Only fails once the counter stopped and the last text was sent
+                if changed.is_err() {
+                    return Ok(());
+                }
+                let value = text.borrow_and_update().clone();
+                request_id += 1;
+                let request = json!({"op": 6, "d": {
+                    "requestType": "SetInputSettings",
+                    "requestId": request_id.to_string(),
+                    "requestData": {"inputName": source, "inputSettings": {"text": value}},
+                }});
+                socket.send(Message::Text(request.to_string())).await?;
+            }
+            message = receive(socket) => {
+                let message = message?;
+                let status = &message["d"]["requestStatus"];
+                if message["op"] == 7 && status["result"] == false {
+                    return Err(format!("OBS rejected setting the source '{source}': {}", status["comment"].as_str().unwrap_or("unknown error")).into());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::WebSocketStream;
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn config(port: u16, password: &str) -> ObsConfig {
+        ObsConfig {
+            port,
+            password: password.to_string(),
+            source: "Timer Text".to_string(),
+            ..ObsConfig::default()
+        }
+    }
+
+    async fn next_json(socket: &mut WebSocketStream<TcpStream>) -> Value {
+        loop {
+            match tokio::time::timeout(TIMEOUT, socket.next()).await.unwrap().unwrap().unwrap() {
+                Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+                Message::Close(_) => panic!("The client closed the connection"),
+                _ => {}
+            }
+        }
+    }
+
+    async fn send_json(socket: &mut WebSocketStream<TcpStream>, value: Value) {
+        socket.send(Message::Text(value.to_string())).await.unwrap();
+    }
+
+    ///Accepts a connection like OBS does. Returns the socket and the Identify message.
+    async fn accept(listener: &TcpListener, auth: Option<(&str, &str)>) -> (WebSocketStream<TcpStream>, Value) {
+        let (stream, _) = tokio::time::timeout(TIMEOUT, listener.accept()).await.unwrap().unwrap();
+        let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let mut hello = json!({"obsWebSocketVersion": "5.0.0", "rpcVersion": 1});
+        if let Some((challenge, salt)) = auth {
+            hello["authentication"] = json!({"challenge": challenge, "salt": salt});
+        }
+        send_json(&mut socket, json!({"op": 0, "d": hello})).await;
+        let identify = next_json(&mut socket).await;
+        (socket, identify)
+    }
+
+    ///Answers the next request successfully and returns the text it set.
+    async fn next_text(socket: &mut WebSocketStream<TcpStream>) -> String {
+        let request = next_json(socket).await;
+        assert_eq!(request["op"], 6);
+        assert_eq!(request["d"]["requestType"], "SetInputSettings");
+        assert_eq!(request["d"]["requestData"]["inputName"], "Timer Text");
+        send_json(socket, json!({"op": 7, "d": {
+            "requestType": "SetInputSettings",
+            "requestId": request["d"]["requestId"],
+            "requestStatus": {"result": true, "code": 100},
+        }})).await;
+        request["d"]["requestData"]["inputSettings"]["text"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn authentication_example() {
+        //From the obs-websocket protocol documentation
+        assert_eq!(
+            authentication("supersecretpassword", "lM1GncleQOaCu9lT1yeUZhFYnqhsLLP1G5lAGo3ixaI=", "+IxH4CnCiqpX1rM9scsNynZzbOe4KhDeYcTNS3PDaeY="),
+            "1Ct943GAT+6YQUUX47Ia/ncufilbe6+oD6lY+5kaCu4="
+        );
+    }
+
+    #[tokio::test]
+    async fn identify_and_forward() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sink = ObsSink::start(config(listener.local_addr().unwrap().port(), "secret"), Arc::from("Timer"), Default::default());
+        sink.set_text("00:05:00");
+        let (mut socket, identify) = accept(&listener, Some(("challenge", "salt"))).await;
+        assert_eq!(identify["op"], 1);
+        assert_eq!(identify["d"]["rpcVersion"], RPC_VERSION);
+        assert_eq!(identify["d"]["authentication"], authentication("secret", "salt", "challenge"));
+        send_json(&mut socket, json!({"op": 2, "d": {"negotiatedRpcVersion": 1}})).await;
+
+        assert_eq!(next_text(&mut socket).await, "00:05:00");
+        sink.set_text("00:04:59");
+        assert_eq!(next_text(&mut socket).await, "00:04:59");
+        assert_eq!(sink.error(), None);
+
+        //The last text is delivered, then the connection closes without waiting for an answer
+        sink.set_text("00:04:58");
+        drop(sink);
+        let request = next_json(&mut socket).await;
+        assert_eq!(request["d"]["requestData"]["inputSettings"]["text"], "00:04:58");
+    }
+
+    #[tokio::test]
+    async fn without_authentication() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sink = ObsSink::start(config(listener.local_addr().unwrap().port(), ""), Arc::from("Timer"), Default::default());
+        let (mut socket, identify) = accept(&listener, None).await;
+        assert_eq!(identify["d"].get("authentication"), None);
+        send_json(&mut socket, json!({"op": 2, "d": {"negotiatedRpcVersion": 1}})).await;
+        sink.set_text("1");
+        assert_eq!(next_text(&mut socket).await, "1");
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_drop() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sink = ObsSink::start(config(listener.local_addr().unwrap().port(), ""), Arc::from("Timer"), Default::default());
+        sink.set_text("first");
+        let (mut socket, _) = accept(&listener, None).await;
+        send_json(&mut socket, json!({"op": 2, "d": {"negotiatedRpcVersion": 1}})).await;
+        assert_eq!(next_text(&mut socket).await, "first");
+        drop(socket);
+
+        sink.set_text("second");
+        let (mut socket, _) = accept(&listener, None).await;
+        assert!(sink.error().is_some());
+        send_json(&mut socket, json!({"op": 2, "d": {"negotiatedRpcVersion": 1}})).await;
+        //The current text is sent right after reconnecting
+        assert_eq!(next_text(&mut socket).await, "second");
+        assert_eq!(sink.error(), None);
+    }
+
+    #[tokio::test]
+    async fn rejected_identify() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sink = ObsSink::start(config(listener.local_addr().unwrap().port(), "wrong"), Arc::from("Timer"), Default::default());
+        let (mut socket, _) = accept(&listener, Some(("challenge", "salt"))).await;
+        //OBS closes the connection with 4009 AuthenticationFailed
+        socket.close(None).await.unwrap();
+        let mut error = sink.error.clone();
+        tokio::time::timeout(TIMEOUT, error.wait_for(Option::is_some)).await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,374 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use crate::app::popup::{handle_display_popup_arc, ArcPopupStore};
+use crate::counter_or_timer::format::{Template, TemplateError, Values, TEMPLATE_HELP};
+use crate::counter_or_timer::obs::{ObsConfig, ObsSink};
+use crate::counter_or_timer::CounterTimer;
+use file::FileTarget;
+use stream::{FifoTarget, NetworkTarget};
+
+mod file;
+mod stream;
+
+pub use file::{WriteMode, WRITE_MODES};
+pub(crate) use file::replace_file;
+pub use stream::{Protocol, PROTOCOLS};
+
+const MIN_RETRY: Duration = Duration::from_secs(1);
+const MAX_RETRY: Duration = Duration::from_secs(60);
+///A write taking longer counts as failed, so that a stuck output doesn't hold up the counter.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+///Where an output sends the value.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(tag = "kind")]
+pub enum Destination {
+    File {
+        #[serde(default)]
+        path: PathBuf,
+        #[serde(default)]
+        write_mode: WriteMode,
+    },
+    ///One line per value
+    Stdout,
+    ///A named pipe, that gets one line per value
+    Fifo {
+        #[serde(default)]
+        path: PathBuf,
+    },
+    ///`host:port`, that gets one line per value
+    Network {
+        #[serde(default)]
+        address: String,
+        #[serde(default)]
+        protocol: Protocol,
+    },
+    ///A text source in OBS
+    Obs(ObsConfig),
+}
+
+impl Destination {
+    ///One of each kind, for choosing the kind in the ui
+    fn kinds() -> [Self; 5] {
+        [
+            Self::File { path: PathBuf::new(), write_mode: WriteMode::default() },
+            Self::Stdout,
+            Self::Fifo { path: PathBuf::new() },
+            Self::Network { address: String::new(), protocol: Protocol::default() },
+            Self::Obs(ObsConfig::default()),
+        ]
+    }
+    const fn kind(&self) -> &'static str {
+        match self {
+            Self::File { .. } => "File",
+            Self::Stdout => "Stdout",
+            Self::Fifo { .. } => "FIFO",
+            Self::Network { .. } => "Network",
+            Self::Obs(_) => "OBS",
+        }
+    }
+    ///The path of a destination, that a file dialog can pick
+    pub fn path_mut(&mut self) -> Option<&mut PathBuf> {
+        match self {
+            Self::File { path, .. } | Self::Fifo { path } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Destination {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File { path, .. } => write!(f, "File {}", path.display()),
+            Self::Stdout => write!(f, "Stdout"),
+            Self::Fifo { path } => write!(f, "FIFO {}", path.display()),
+            Self::Network { address, protocol } => write!(f, "{protocol} {address}"),
+            Self::Obs(config) => write!(f, "OBS source {}", config.source),
+        }
+    }
+}
+
+///One of the places a counter writes its value to.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct OutputConfig {
+    #[serde(flatten)]
+    pub destination: Destination,
+    ///Replaces the counter's format for this output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            destination: Destination::kinds()[0].clone(),
+            format: None,
+        }
+    }
+}
+
+impl OutputConfig {
+    ///Returns true, if a file should be picked for the output.
+    fn ui(&mut self, ui: &mut egui::Ui, id: impl std::hash::Hash + Copy, counter_format: &str) -> bool {
+        let mut pick = false;
+        ui.horizontal(|ui|{
+            egui::ComboBox::from_id_source((id, "kind"))
+                .selected_text(self.destination.kind())
+                .show_ui(ui, |ui| for kind in Destination::kinds() {
+                    if ui.selectable_label(self.destination.kind() == kind.kind(), kind.kind()).clicked() && self.destination.kind() != kind.kind() {
+                        self.destination = kind;
+                    }
+                });
+            match &mut self.destination {
+                Destination::File { path, write_mode } => {
+                    ui.label(path.to_string_lossy());
+                    pick = ui.button("Select File").clicked();
+                    egui::ComboBox::from_id_source((id, "write_mode"))
+                        .selected_text(write_mode.to_string())
+                        .show_ui(
+                            ui,
+                            |ui| for mode in WRITE_MODES {
+                                ui.selectable_value(write_mode, *mode, mode.to_string())
+                                    .on_hover_text(mode.get_desc());
+                            }
+                        ).response.on_hover_text(write_mode.get_desc());
+                }
+                Destination::Stdout => {
+                    ui.label("Prints one line per value.");
+                }
+                Destination::Fifo { path } => {
+                    ui.label(path.to_string_lossy());
+                    pick = ui.button("Select FIFO").clicked();
+                }
+                Destination::Network { address, protocol } => {
+                    ui.add(egui::TextEdit::singleline(address).hint_text("host:port"));
+                    egui::ComboBox::from_id_source((id, "protocol"))
+                        .selected_text(protocol.to_string())
+                        .show_ui(ui, |ui| for value in PROTOCOLS {
+                            ui.selectable_value(protocol, *value, value.to_string());
+                        });
+                }
+                Destination::Obs(_) => {}
+            }
+        });
+        if let Destination::Obs(config) = &mut self.destination {
+            config.ui(ui, (id, "obs"));
+        }
+        ui.horizontal(|ui|{
+            let mut own = self.format.is_some();
+            if ui.checkbox(&mut own, "Own format").on_hover_text("Otherwise the counter's format is used").changed() {
+                self.format = own.then(|| counter_format.to_string());
+            }
+            if let Some(format) = &mut self.format {
+                ui.text_edit_singleline(format).on_hover_text(TEMPLATE_HELP);
+                if let Err(err) = Template::from_str(format) {
+                    ui.colored_label(ui.visuals().error_fg_color, err.to_string());
+                }
+            }
+        });
+        pick
+    }
+}
+
+///Outcome of the recent writes of one output, shown next to the counter.
+#[derive(Clone, Debug, Default, Serialize, Eq, PartialEq)]
+pub struct WriteStatus {
+    pub output: String,
+    pub last_write: Option<DateTime<Utc>>,
+    ///Cleared by the next successful write
+    pub error: Option<String>,
+}
+
+enum Target {
+    File(FileTarget),
+    Stdout,
+    Fifo(FifoTarget),
+    Network(NetworkTarget),
+    Obs(ObsSink),
+}
+
+impl Target {
+    async fn write(&mut self, text: &str) -> std::io::Result<()> {
+        match self {
+            Self::File(file) => file.write(text).await,
+            Self::Stdout => stream::write_stdout(text).await,
+            Self::Fifo(fifo) => fifo.write(text).await,
+            Self::Network(network) => network.write(text).await,
+            Self::Obs(obs) => {
+                obs.set_text(text);
+                Ok(())
+            }
+        }
+    }
+    fn reset(&mut self) {
+        match self {
+            Self::File(file) => file.reset(),
+            Self::Fifo(fifo) => fifo.reset(),
+            Self::Network(network) => network.reset(),
+            Self::Stdout | Self::Obs(_) => {}
+        }
+    }
+}
+
+///Limits error popups to one every [`crate::NOTIFICATION_TIMEOUT`] seconds, so a failing output doesn't flood the ui.
+#[derive(Default)]
+pub(crate) struct ErrorThrottle {
+    ///When the last error popup was shown
+    last_message: Option<Instant>,
+}
+
+impl ErrorThrottle {
+    ///Logs the error and shows it, unless an error was shown recently.
+    pub fn report(&mut self, output: &str, err: &impl Display, popups: &ArcPopupStore) {
+        if self.last_message.is_some_and(|instant: Instant| instant.elapsed().as_secs() <= crate::NOTIFICATION_TIMEOUT) {
+            return;
+        }
+        self.last_message = Some(Instant::now());
+        log::error!("Error writing to {output}: {err}");
+        handle_display_popup_arc(
+            popups,
+            format!("Could not write to {output}"),
+            err,
+            "Error Writing"
+        );
+    }
+}
+
+///A running output.
+///Failed writes are retried with an increasing delay, without affecting the other outputs.
+struct Output {
+    template: Option<Template>,
+    target: Target,
+    retry_at: Option<Instant>,
+    backoff: Duration,
+    errors: ErrorThrottle,
+    status: WriteStatus,
+}
+
+impl Output {
+    fn new(config: &OutputConfig, name: &Arc<str>, popups: &ArcPopupStore) -> Result<Self, TemplateError> {
+        let template = config.format.as_deref().map(Template::from_str).transpose()?;
+        let target = match &config.destination {
+            Destination::File { path, write_mode } => Target::File(FileTarget::new(path.clone(), *write_mode)),
+            Destination::Stdout => Target::Stdout,
+            Destination::Fifo { path } => Target::Fifo(FifoTarget::new(path.clone())),
+            Destination::Network { address, protocol } => Target::Network(NetworkTarget::new(address.clone(), *protocol)),
+            //Reports it's own connection errors
+            Destination::Obs(config) => Target::Obs(ObsSink::start(config.clone(), name.clone(), popups.clone())),
+        };
+        Ok(Self {
+            template,
+            target,
+            retry_at: None,
+            backoff: MIN_RETRY,
+            errors: ErrorThrottle::default(),
+            status: WriteStatus { output: config.destination.to_string(), last_write: None, error: None },
+        })
+    }
+
+    ///Writes `text`, or the value in the output's own format, if there is one and `values` are given.
+    ///Errors are shown at most once every [`crate::NOTIFICATION_TIMEOUT`] seconds.
+    async fn write(&mut self, text: &str, values: Option<&Values<'_>>, popups: &ArcPopupStore) {
+        if self.retry_at.is_some_and(|retry_at| Instant::now() < retry_at) {
+            return;
+        }
+        let own;
+        let text = match (&self.template, values) {
+            (Some(template), Some(values)) => {
+                own = template.render(values);
+                &own
+            }
+            _ => text,
+        };
+        let result = match tokio::time::timeout(WRITE_TIMEOUT, self.target.write(text)).await {
+            Ok(result) => result,
+            Err(_) => Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Writing took too long")),
+        };
+        match result {
+            Ok(()) => {
+                self.retry_at = None;
+                self.backoff = MIN_RETRY;
+                self.status.last_write = Some(Utc::now());
+                self.status.error = match &self.target {
+                    Target::Obs(obs) => obs.error(),
+                    _ => None,
+                };
+            }
+            Err(err) => {
+                self.target.reset();
+                self.retry_at = Some(Instant::now() + self.backoff);
+                self.backoff = (self.backoff * 2).min(MAX_RETRY);
+                self.status.error = Some(err.to_string());
+                self.errors.report(&self.status.output, &err, popups);
+            }
+        }
+    }
+}
+
+///All outputs of a running counter.
+pub(super) struct Outputs(Vec<Output>);
+
+impl Outputs {
+    ///Fails with the name of the output, that has an invalid format.
+    pub fn new(configs: &[OutputConfig], name: &Arc<str>, popups: &ArcPopupStore) -> Result<Self, (String, TemplateError)> {
+        configs.iter()
+            .map(|config| Output::new(config, name, popups).map_err(|err| (config.destination.to_string(), err)))
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    ///Writes to every output at once, so that a slow one doesn't delay the others.
+    ///Without `values`, `text` replaces the value in every output.
+    pub async fn write(&mut self, text: &str, values: Option<&Values<'_>>, popups: &ArcPopupStore) {
+        futures_util::future::join_all(self.0.iter_mut().map(|output| output.write(text, values, popups))).await;
+    }
+
+    pub fn status(&self) -> Vec<WriteStatus> {
+        self.0.iter().map(|output| output.status.clone()).collect()
+    }
+}
+
+impl CounterTimer {
+    pub(super) fn outputs_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.outputs_open;
+        egui::Window::new(format!("Outputs of {}", self.name))
+            .id(egui::Id::new((self.id, "outputs")))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                if self.counter.is_some() {
+                    ui.label("Stop the counter to edit its outputs.");
+                }
+                ui.add_enabled_ui(self.counter.is_none(), |ui| {
+                    let mut remove = None;
+                    let mut pick = None;
+                    for (index, output) in self.outputs.iter_mut().enumerate() {
+                        ui.push_id((self.id, "output", index), |ui| {
+                            if output.ui(ui, (self.id, index), &self.format) {
+                                pick = Some(index);
+                            }
+                            if ui.button("Delete").clicked() {
+                                remove = Some(index);
+                            }
+                        });
+                        ui.separator();
+                    }
+                    if let Some(index) = pick {
+                        self.pick_file(index);
+                    }
+                    if let Some(index) = remove {
+                        self.outputs.remove(index);
+                    }
+                    if ui.button("Add Output").clicked() {
+                        self.outputs.push(OutputConfig::default());
+                    }
+                });
+            });
+        self.outputs_open = open;
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt::{Display, Formatter};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+pub const WRITE_MODES:&[WriteMode] = &[WriteMode::Replace, WriteMode::Truncate];
+
+///How a new value gets into the output file.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, Eq, PartialEq, Hash)]
+pub enum WriteMode {
+    //Writes a temporary file next to the output and renames it over the output.
+    //Readers always see a complete value.
+    #[default]
+    Replace,
+    //Overwrites the open file in place and cuts off what is left of the old value.
+    //Not atomic: readers can briefly see the new value followed by the end of the old one.
+    //For file systems or readers, that don't cope with the file being replaced.
+    Truncate,
+}
+
+impl WriteMode {
+    pub const fn get_desc(self) -> &'static str {
+        match self {
+            Self::Replace => "Write a temporary file and rename it over the output. Readers never see a partial value.",
+            Self::Truncate => "Overwrite the file in place and truncate it afterward. Not atomic: while writing, readers can see the new value followed by the end of a longer old value. Use this only, if renaming files is not possible (e.g. the file is held open on Windows, or it is a special file).",
+        }
+    }
+}
+
+impl Display for WriteMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Replace => write!(f, "Replace atomically"),
+            Self::Truncate => write!(f, "Overwrite in place"),
+        }
+    }
+}
+
+///A file, that always contains just the latest value.
+///The file is reopened, if it was deleted or replaced.
+pub(super) struct FileTarget {
+    path: PathBuf,
+    mode: WriteMode,
+    ///Only open for [`WriteMode::Truncate`]
+    file: Option<tokio::fs::File>,
+}
+
+///`.<name>.tmp` in the same directory, so that the rename doesn't cross file systems.
+fn temp_path(path: &Path) -> std::io::Result<PathBuf> {
+    let Some(name) = path.file_name() else {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "No file was selected"));
+    };
+    let mut temp = std::ffi::OsString::from(".");
+    temp.push(name);
+    temp.push(".tmp");
+    Ok(path.with_file_name(temp))
+}
+
+///Writes a temporary file next to `path` and renames it over `path`, so that readers always see complete contents.
+pub(crate) async fn replace_file(path: &Path, text: &str) -> std::io::Result<()> {
+    let temp = temp_path(path)?;
+    tokio::fs::write(temp.as_path(), text).await?;
+    tokio::fs::rename(temp.as_path(), path).await
+}
+
+impl FileTarget {
+    pub const fn new(path: PathBuf, mode: WriteMode) -> Self {
+        Self {
+            path,
+            mode,
+            file: None,
+        }
+    }
+
+    ///Starts over with a fresh handle next time
+    pub fn reset(&mut self) {
+        self.file = None;
+    }
+
+    ///Replaces the contents of the file with `text`.
+    pub async fn write(&mut self, text: &str) -> std::io::Result<()> {
+        match self.mode {
+            WriteMode::Replace => replace_file(&self.path, text).await,
+            WriteMode::Truncate => {
+                let file = match self.file.take() {
+                    Some(file) if !self.replaced(&file).await => file,
+                    old => {
+                        if old.is_some() {
+                            log::info!("{} was deleted or replaced. Reopening it.", self.path.display());
+                        }
+                        tokio::fs::OpenOptions::new()
+                            .write(true)
+                            .truncate(true)
+                            .create(true)
+                            .open(self.path.as_path())
+                            .await?
+                    }
+                };
+                let file = self.file.insert(file);
+                file.seek(SeekFrom::Start(0)).await?;
+                file.write_all(text.as_bytes()).await?;
+                //Truncating after writing means, that readers never see an empty file.
+                //Until then they see the new text followed by the rest of a longer old text.
+                file.set_len(text.len() as u64).await?;
+                file.flush().await
+            }
+        }
+    }
+
+    ///Whether the path no longer leads to the open file.
+    async fn replaced(&self, file: &tokio::fs::File) -> bool {
+        let Ok(current) = tokio::fs::metadata(self.path.as_path()).await else {
+            return true;
+        };
+        let Ok(open) = file.metadata().await else {
+            return false;
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            open.dev() != current.dev() || open.ino() != current.ino()
+        }
+        //File ids are not available on stable Rust. A different creation time or size means another file.
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::MetadataExt;
+            open.creation_time() != current.creation_time() || open.file_size() != current.file_size()
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            open.len() != current.len()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn truncate_reopens_replaced_file() {
+        let dir = std::env::temp_dir().join(format!("counter-file-test-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("value.txt");
+        let mut target = FileTarget::new(path.clone(), WriteMode::Truncate);
+        target.write("00:10:00").await.unwrap();
+        target.write("9").await.unwrap();
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), "9");
+
+        //Another program replaces the file
+        let other = dir.join("other.txt");
+        tokio::fs::write(&other, "replaced").await.unwrap();
+        tokio::fs::rename(&other, &path).await.unwrap();
+        let file = target.file.as_ref().unwrap();
+        assert!(target.replaced(file).await);
+        target.write("8").await.unwrap();
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), "8");
+        assert!(!target.replaced(target.file.as_ref().unwrap()).await);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
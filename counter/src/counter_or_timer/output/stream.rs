@@ -0,0 +1,122 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+
+pub const PROTOCOLS:&[Protocol] = &[Protocol::Tcp, Protocol::Udp];
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, Eq, PartialEq, Hash)]
+pub enum Protocol {
+    ///One line per value over a connection
+    #[default]
+    Tcp,
+    ///One datagram per value
+    Udp,
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp => write!(f, "TCP"),
+            Self::Udp => write!(f, "UDP"),
+        }
+    }
+}
+
+pub(super) async fn write_stdout(text: &str) -> std::io::Result<()> {
+    let mut stdout = tokio::io::stdout();
+    stdout.write_all(format!("{text}\n").as_bytes()).await?;
+    stdout.flush().await
+}
+
+///A named pipe, that gets one line per value.
+pub(super) struct FifoTarget {
+    path: PathBuf,
+    #[cfg(unix)]
+    sender: Option<tokio::net::unix::pipe::Sender>,
+}
+
+impl FifoTarget {
+    pub const fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            #[cfg(unix)]
+            sender: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        #[cfg(unix)]
+        {
+            self.sender = None;
+        }
+    }
+
+    ///Fails, while nothing reads from the pipe.
+    #[cfg(unix)]
+    pub async fn write(&mut self, text: &str) -> std::io::Result<()> {
+        let sender = match &mut self.sender {
+            Some(sender) => sender,
+            None => self.sender.insert(tokio::net::unix::pipe::OpenOptions::new().open_sender(&self.path)?),
+        };
+        sender.write_all(format!("{text}\n").as_bytes()).await
+    }
+
+    #[cfg(not(unix))]
+    pub async fn write(&mut self, _text: &str) -> std::io::Result<()> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, format!("Writing to the FIFO {} is only supported on unix", self.path.display())))
+    }
+}
+
+///A tcp or udp receiver, that gets one line per value.
+pub(super) struct NetworkTarget {
+    address: String,
+    protocol: Protocol,
+    tcp: Option<TcpStream>,
+    udp: Option<UdpSocket>,
+}
+
+impl NetworkTarget {
+    pub const fn new(address: String, protocol: Protocol) -> Self {
+        Self {
+            address,
+            protocol,
+            tcp: None,
+            udp: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.tcp = None;
+        self.udp = None;
+    }
+
+    pub async fn write(&mut self, text: &str) -> std::io::Result<()> {
+        let line = format!("{text}\n");
+        match self.protocol {
+            Protocol::Tcp => {
+                let stream = match &mut self.tcp {
+                    Some(stream) => stream,
+                    None => self.tcp.insert(TcpStream::connect(self.address.as_str()).await?),
+                };
+                stream.write_all(line.as_bytes()).await
+            }
+            Protocol::Udp => {
+                let socket = match &mut self.udp {
+                    Some(socket) => socket,
+                    None => {
+                        let Some(addr) = tokio::net::lookup_host(self.address.as_str()).await?.next() else {
+                            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} could not be resolved", self.address)));
+                        };
+                        let bind = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+                        let socket = UdpSocket::bind(bind).await?;
+                        socket.connect(addr).await?;
+                        self.udp.insert(socket)
+                    }
+                };
+                socket.send(line.as_bytes()).await.map(|_| ())
+            }
+        }
+    }
+}
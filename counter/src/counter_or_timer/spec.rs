@@ -0,0 +1,221 @@
+//! The file format for sharing counters.
+//!
+//! A counter set is a json object like this:
+//! ```json
+//! {
+//!   "version": 2,
+//!   "counters": [
+//!     {
+//!       "name": "Break",
+//!       "mode": "Timer",
+//!       "start_seconds": 300.0,
+//!       "outputs": [
+//!         {"kind": "File", "path": "/home/me/obs/break.txt", "write_mode": "Replace"},
+//!         {"kind": "Network", "address": "127.0.0.1:9000", "protocol": "Udp", "format": "{S}"}
+//!       ],
+//!       "format": "{m:2}:{s:2}",
+//!       "period_ms": 1000,
+//!       "at_zero": "Stop"
+//!     }
+//!   ]
+//! }
+//! ```
+//! Every field of a counter except `name` is optional and defaults to what a new counter uses.
+//! Names must not be empty, and every name may only be used once.
+//! - `mode`: `Counter`, `Timer`, `SystemTime`, `Deadline` or `Tally`
+//! - `start_seconds`: the start value in seconds. May be negative or fractional. For `Tally`, the count to start from.
+//! - `deadline`: for `Deadline`, e.g. `{"date": "2024-12-31", "time": "23:59:59", "tz": "Europe/Berlin"}`. `tz` is an IANA time zone, or `null` for the local time zone.
+//! - `outputs`: where the value is written to. `kind` is one of
+//!   - `File` with `path` and `write_mode`: `Replace` (atomic) or `Truncate` (in place)
+//!   - `Stdout`
+//!   - `Fifo` with `path`
+//!   - `Network` with `address` (`host:port`) and `protocol`: `Tcp` or `Udp`
+//!   - `Obs` with `host`, `port`, `password` and `source`
+//!
+//!   An output with a `format` uses it instead of the counter's.
+//! - `file`, `write_mode`: version 1 had a single file instead of `outputs`. Still read, if there are no `outputs`.
+//! - `format`: the output template, see [`super::format::TEMPLATE_HELP`]
+//! - `period_ms`: how often the value is updated, in milliseconds
+//! - `at_zero`: for `Timer`, one of `Continue`, `Stop`, `Overtime` or `Restart`
+//! - `restarts`: how often `Restart` restarts the Timer
+//! - `step`: how much the buttons of a `Tally` change the count
+//! - `adjust_seconds`: the amounts of the quick adjust buttons, in seconds
+//! - `actions`: the actions as saved by the app. `RunCommand` actions are only imported, after they were shown and allowed in the import dialog.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use serde::{Deserialize, Serialize};
+use crate::counter_or_timer::{default_format, default_period, default_step, seconds_to_nanos, AtZero, CounterTimer, Mode, NANOS_IN_SECOND};
+use crate::counter_or_timer::action::{Action, EventAction};
+use crate::counter_or_timer::adjust::default_adjustments;
+use crate::counter_or_timer::deadline::Deadline;
+use crate::counter_or_timer::output::{Destination, OutputConfig, WriteMode};
+
+///The newest version of the file format, that this app understands.
+pub const SET_VERSION: u32 = 2;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CounterSet {
+    pub version: u32,
+    pub counters: Vec<CounterSpec>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CounterSpec {
+    pub name: String,
+    #[serde(default)]
+    mode: Mode,
+    #[serde(default)]
+    start_seconds: f64,
+    #[serde(default)]
+    deadline: Deadline,
+    #[serde(default)]
+    outputs: Vec<OutputConfig>,
+    #[serde(default, skip_serializing)]
+    file: PathBuf,
+    #[serde(default, skip_serializing)]
+    write_mode: WriteMode,
+    #[serde(default = "default_format")]
+    format: String,
+    #[serde(default = "default_period_ms")]
+    period_ms: u64,
+    #[serde(default)]
+    at_zero: AtZero,
+    #[serde(default)]
+    restarts: u32,
+    #[serde(default = "default_step")]
+    step: i64,
+    #[serde(default = "default_adjust_seconds")]
+    adjust_seconds: Vec<f64>,
+    #[serde(default)]
+    actions: Vec<EventAction>,
+}
+
+fn default_period_ms() -> u64 {
+    u64::try_from(default_period().as_millis()).unwrap_or(u64::MAX)
+}
+
+fn default_adjust_seconds() -> Vec<f64> {
+    default_adjustments().into_iter().map(|ns| ns as f64 / NANOS_IN_SECOND as f64).collect()
+}
+
+impl CounterSet {
+    ///Checks, that every counter has a name, that no other counter in the set has.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut names = HashSet::new();
+        for (index, counter) in self.counters.iter().enumerate() {
+            if counter.name.trim().is_empty() {
+                return Err(format!("Counter number {} has no name", index + 1));
+            }
+            if !names.insert(counter.name.as_str()) {
+                return Err(format!("There is more than one Counter named '{}'", counter.name));
+            }
+        }
+        Ok(())
+    }
+    ///Every shell command the counters run, with the name of the counter.
+    pub fn commands(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.counters.iter().flat_map(|counter| counter.actions.iter().filter_map(|action| match &action.action {
+            Action::RunCommand(command) => Some((counter.name.as_str(), command.as_str())),
+            _ => None,
+        }))
+    }
+    ///Removes every action, that runs a shell command.
+    pub fn strip_commands(&mut self) {
+        for counter in &mut self.counters {
+            counter.actions.retain(|action| !matches!(action.action, Action::RunCommand(_)));
+        }
+    }
+}
+
+impl CounterTimer {
+    pub fn to_spec(&self) -> CounterSpec {
+        CounterSpec {
+            name: self.name.to_string(),
+            mode: self.mode,
+            start_seconds: if self.mode == Mode::Tally {
+                self.initial_ns as f64
+            } else {
+                self.initial_ns as f64 / NANOS_IN_SECOND as f64
+            },
+            deadline: self.deadline,
+            outputs: self.outputs.clone(),
+            file: PathBuf::new(),
+            write_mode: WriteMode::default(),
+            format: self.format.clone(),
+            period_ms: u64::try_from(self.period.as_millis()).unwrap_or(u64::MAX),
+            at_zero: self.at_zero,
+            restarts: self.restarts,
+            step: self.step,
+            adjust_seconds: self.adjustments.iter().map(|ns| *ns as f64 / NANOS_IN_SECOND as f64).collect(),
+            actions: self.actions.clone(),
+        }
+    }
+
+    ///Replaces the configuration with the imported one. The counter is stopped and reset.
+    pub fn apply_spec(&mut self, spec: CounterSpec) {
+        self.stop_counter();
+        self.name = spec.name.into();
+        self.mode = spec.mode;
+        self.deadline = spec.deadline;
+        self.outputs = spec.outputs;
+        if self.outputs.is_empty() && !spec.file.as_os_str().is_empty() {
+            self.outputs.push(OutputConfig {
+                destination: Destination::File { path: spec.file, write_mode: spec.write_mode },
+                format: None,
+            });
+        }
+        self.format = spec.format;
+        //A period of 0 would make the counter spin
+        self.period = std::time::Duration::from_millis(spec.period_ms.max(1));
+        self.at_zero = spec.at_zero;
+        self.restarts = spec.restarts;
+        self.restarts_left.store(spec.restarts, Ordering::Release);
+        self.step = spec.step.max(1);
+        self.adjustments = spec.adjust_seconds.into_iter().map(seconds_to_nanos).collect();
+        self.actions = spec.actions;
+        if self.mode == Mode::Tally {
+            //Saturating conversion
+            self.set_start_value(spec.start_seconds as i64);
+        } else {
+            self.set_start_value(seconds_to_nanos(spec.start_seconds));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(counters: &str) -> serde_json::Result<CounterSet> {
+        serde_json::from_str(&format!(r#"{{"version": 2, "counters": {counters}}}"#))
+    }
+
+    #[test]
+    fn name_is_required() {
+        assert!(set(r#"[{"mode": "Timer"}]"#).is_err());
+        let set = set(r#"[{"name": "Break"}]"#).unwrap();
+        assert_eq!(set.counters[0].format, default_format());
+        assert_eq!(set.counters[0].step, default_step());
+        assert_eq!(set.validate(), Ok(()));
+    }
+
+    #[test]
+    fn invalid_names() {
+        assert!(set(r#"[{"name": "Break"}, {"name": " "}]"#).unwrap().validate().unwrap_err().contains("number 2"));
+        assert!(set(r#"[{"name": "Break"}, {"name": "Break"}]"#).unwrap().validate().unwrap_err().contains("'Break'"));
+    }
+
+    #[test]
+    fn strip_commands() {
+        let mut set = set(r#"[{"name": "Break", "actions": [
+            {"trigger": "Zero", "action": {"RunCommand": "rm -rf ~"}},
+            {"trigger": "Zero", "action": {"Popup": "Break is over"}}
+        ]}]"#).unwrap();
+        assert_eq!(set.commands().collect::<Vec<_>>(), [("Break", "rm -rf ~")]);
+        set.strip_commands();
+        assert_eq!(set.commands().count(), 0);
+        assert_eq!(set.counters[0].actions.len(), 1);
+    }
+}
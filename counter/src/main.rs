@@ -1,12 +1,16 @@
 #![forbid(unsafe_code)]
 #![windows_subsystem = "windows"]
 mod app;
+mod chat;
 mod counter_or_timer;
+mod server;
 
+use std::path::PathBuf;
 use std::sync::OnceLock;
 use std::time::Duration;
 use tokio::runtime::{Builder, Runtime};
 
+pub const APP_NAME:&str = "Counter";
 pub const NOTIFICATION_TIMEOUT:u64 = 30;
 pub const PERIOD:Duration = Duration::from_secs(1);
 
@@ -21,12 +25,47 @@ fn get_runtime() -> &'static Runtime {
             .expect("Failed to initialize tokio runtime")
     })
 }
+const USAGE:&str = "Usage: counter [--headless --config <counters.json>]
+The config is a file written by \"Export…\", or a copy of the saved app state for the http server, chat and combined output settings.";
+
+struct Args {
+    ///Path to a config file, if the counters should run without a gui
+    headless: Option<PathBuf>,
+    ///Only print the usage
+    help: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut headless = false;
+    let mut config = None;
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--headless") => headless = true,
+            Some("--config") => config = Some(PathBuf::from(args.next().ok_or("--config needs a path")?)),
+            Some("--help" | "-h") => return Ok(Args { headless: None, help: true }),
+            _ => return Err(format!("Unknown argument {}. {USAGE}", arg.to_string_lossy())),
+        }
+    }
+    match (headless, config) {
+        (true, Some(config)) => Ok(Args { headless: Some(config), help: false }),
+        (false, None) => Ok(Args { headless: None, help: false }),
+        (true, None) => Err(format!("--headless needs a config file. {USAGE}")),
+        (false, Some(_)) => Err(format!("--config is only used with --headless. {USAGE}")),
+    }
+}
+
 #[cfg(debug_assertions)]
 const LOG_LEVEL:log::LevelFilter = log::LevelFilter::Debug;
 #[cfg(not(debug_assertions))]
 const LOG_LEVEL:log::LevelFilter = log::LevelFilter::Info;
 
 fn main() -> Result<(), ()> {
+    let args = parse_args();
+    if args.as_ref().is_ok_and(|args| args.help) {
+        println!("{USAGE}");
+        return Ok(());
+    }
     simple_logger::SimpleLogger::new()
         .with_utc_timestamps()
         .with_colors(true)
@@ -36,12 +75,18 @@ fn main() -> Result<(), ()> {
         .init()
         .expect("Failed to initialize logger");
     log::info!("Logger initialized");
+    let args = args.map_err(|err| log::error!("{err}"))?;
     let rt = get_runtime();
     let _a = rt.enter(); // "_" as a variable name immediately drops the value, causing no tokio runtime to be registered. "_a" does not.
     log::info!("Tokio Runtime initialized");
+    if let Some(config) = args.headless {
+        app::headless::run(&config)?;
+        log::info!("All Counters stopped. Thank you for using this counter app!");
+        return Ok(());
+    }
     let native_options = eframe::NativeOptions::default();
     if let Some(err) = eframe::run_native(
-        "Counter",
+        APP_NAME,
         native_options,
         Box::new(|cc| Box::new(app::App::new(cc))),
     ).err() {
@@ -0,0 +1,249 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use crate::app::command::CommandSender;
+use crate::app::popup::{handle_display_popup_arc, ArcPopupStore};
+use crate::app::registry::Registry;
+use subathon::{AdjustmentLog, SubathonConfig};
+
+mod control;
+pub(crate) mod subathon;
+
+pub(crate) const DEFAULT_PORT: u16 = 8787;
+const DEFAULT_CSS: &str = "#counter {
+    font-family: sans-serif;
+    font-size: 64px;
+    color: white;
+}
+body[data-running=\"false\"] #counter {
+    opacity: 0.6;
+}";
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct ServerConfig {
+    pub enabled: bool,
+    pub port: u16,
+    ///Added to every overlay page
+    pub css: String,
+    ///Required as a bearer token to control counters. Empty means no token is required.
+    pub token: String,
+    pub subathon: SubathonConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+            css: DEFAULT_CSS.to_string(),
+            token: String::new(),
+            subathon: SubathonConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ServerState {
+    registry: Registry,
+    commands: CommandSender,
+    config: Arc<RwLock<ServerConfig>>,
+    subathon_log: AdjustmentLog,
+    ///Changes (errors), once the server is shutting down
+    shutdown: watch::Receiver<()>,
+}
+
+impl ServerState {
+    fn config(&self) -> ServerConfig {
+        self.config.read().unwrap_or_else(|err| err.into_inner()).clone()
+    }
+}
+
+///A running http server. Shuts down when dropped.
+pub(crate) struct Server {
+    port: u16,
+    config: Arc<RwLock<ServerConfig>>,
+    _shutdown: watch::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Server {
+    ///Starts serving on localhost. Errors are shown as popups.
+    pub fn start(config: &ServerConfig, registry: Registry, commands: CommandSender, subathon_log: AdjustmentLog, popups: ArcPopupStore) -> Self {
+        let port = config.port;
+        let config = Arc::new(RwLock::new(config.clone()));
+        let (shutdown_send, shutdown) = watch::channel(());
+        let state = ServerState {
+            registry,
+            commands,
+            config: config.clone(),
+            subathon_log,
+            shutdown: shutdown.clone(),
+        };
+        let handle = tokio::spawn(async move {
+            let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+            let listener = match tokio::net::TcpListener::bind(addr).await {
+                Ok(listener) => listener,
+                Err(err) => {
+                    log::error!("Failed to bind http server to {addr}: {err}");
+                    handle_display_popup_arc(
+                        &popups,
+                        format!("The http server could not listen on {addr}. Is the port already in use?"),
+                        &err,
+                        "Failed to start http server"
+                    );
+                    return;
+                }
+            };
+            log::info!("Http server listening on http://{addr}");
+            let mut shutdown = shutdown;
+            let result = axum::serve(listener, router(state))
+                .with_graceful_shutdown(async move {
+                    let _ = shutdown.changed().await;
+                })
+                .await;
+            if let Err(err) = result {
+                log::error!("Http server failed: {err}");
+                handle_display_popup_arc(&popups, "The http server stopped unexpectedly", &err, "Http server error");
+            }
+            log::info!("Http server on {addr} stopped");
+        });
+        Self {
+            port,
+            config,
+            _shutdown: shutdown_send,
+            handle,
+        }
+    }
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+    pub fn is_running(&self) -> bool {
+        !self.handle.is_finished()
+    }
+    ///Applies config changes, that don't need a restart.
+    pub fn set_config(&self, config: &ServerConfig) {
+        let mut lock = self.config.write().unwrap_or_else(|err| err.into_inner());
+        if *lock != *config {
+            lock.clone_from(config);
+        }
+    }
+}
+
+fn router(state: ServerState) -> Router {
+    Router::new()
+        .route("/", get(index))
+        .route("/api/counters", get(counters))
+        .route("/api/counters/:name/events", get(events))
+        .route("/overlay/:name", get(overlay))
+        .merge(control::router(state.clone()))
+        .with_state(state)
+}
+
+async fn counters(State(state): State<ServerState>) -> impl IntoResponse {
+    Json(state.registry.snapshot())
+}
+
+///Streams the counter's status as server-sent events, on every change.
+async fn events(State(state): State<ServerState>, Path(name): Path<String>) -> Response {
+    let Some(receiver) = state.registry.find(&name) else {
+        return (StatusCode::NOT_FOUND, format!("There is no counter named '{name}'")).into_response();
+    };
+    let stream = futures_util::stream::unfold(
+        (receiver, state.shutdown, true),
+        |(mut receiver, mut shutdown, first)| async move {
+            if !first {
+                tokio::select! {
+                    changed = receiver.changed() => changed.ok()?,
+                    //End the stream, so that the server can shut down
+                    _ = shutdown.changed() => return None,
+                }
+            }
+            let event = Event::default().json_data(&*receiver.borrow_and_update());
+            Some((event, (receiver, shutdown, false)))
+        },
+    );
+    Sse::new(stream).keep_alive(KeepAlive::default()).into_response()
+}
+
+///Makes text safe to embed in a `<script>` tag as a js string.
+fn js_string(text: &str) -> String {
+    serde_json::to_string(text)
+        .unwrap_or_default()
+        .replace('<', "\\u003c")
+}
+
+async fn overlay(State(state): State<ServerState>, Path(name): Path<String>) -> Response {
+    if state.registry.find(&name).is_none() {
+        return (StatusCode::NOT_FOUND, format!("There is no counter named '{name}'")).into_response();
+    }
+    let css = state.config().css;
+    let name = js_string(&name);
+    Html(format!(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<style>
+html, body {{ margin: 0; background: transparent; }}
+</style>
+<style>
+{css}
+</style>
+</head>
+<body>
+<div id="counter"></div>
+<script>
+const name = {name};
+const counter = document.getElementById("counter");
+document.title = name;
+document.body.dataset.counter = name;
+const events = new EventSource("/api/counters/" + encodeURIComponent(name) + "/events");
+events.onmessage = (event) => {{
+    const status = JSON.parse(event.data);
+    counter.textContent = status.text;
+    document.body.dataset.running = status.running;
+    document.body.dataset.mode = status.mode;
+}};
+</script>
+</body>
+</html>
+"#)).into_response()
+}
+
+async fn index(State(state): State<ServerState>) -> Html<String> {
+    let mut links = String::new();
+    for status in state.registry.snapshot() {
+        let name = js_string(&status.name);
+        links.push_str(&format!("links.push({name});\n"));
+    }
+    Html(format!(r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Counter</title></head>
+<body>
+<h1>Counter Overlays</h1>
+<p>Add one of these pages as a browser source. Counter data is available at <a href="/api/counters">/api/counters</a>.</p>
+<ul id="links"></ul>
+<script>
+const links = [];
+{links}
+for (const name of links) {{
+    const a = document.createElement("a");
+    a.href = "/overlay/" + encodeURIComponent(name);
+    a.textContent = name;
+    const li = document.createElement("li");
+    li.appendChild(a);
+    document.getElementById("links").appendChild(li);
+}}
+</script>
+</body>
+</html>
+"#))
+}
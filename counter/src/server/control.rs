@@ -0,0 +1,130 @@
+use std::sync::Arc;
+use axum::extract::{Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use crate::app::command::{CommandError, Operation};
+use crate::counter_or_timer::seconds_to_nanos;
+use crate::server::ServerState;
+
+///Routes to control counters, like the buttons in the ui do, and the subathon webhook.
+///Protected by the bearer token from the [`crate::server::ServerConfig`], if one is set,
+///and closed to requests from other websites.
+pub(super) fn router(state: ServerState) -> Router<ServerState> {
+    Router::new()
+        .route("/api/counters/:name/start", post(start))
+        .route("/api/counters/:name/stop", post(stop))
+        .route("/api/counters/:name/reset", post(reset))
+        .route("/api/counters/:name/add", post(add))
+        .route("/api/counters/:name/set", post(set))
+        .route("/api/webhook/subathon", post(super::subathon::webhook))
+        .route_layer(axum::middleware::from_fn_with_state(state, authorize))
+}
+
+///Whether a browser `Origin` is one of the server's own pages.
+fn is_own_origin(origin: &str, port: u16) -> bool {
+    ["localhost", "127.0.0.1", "[::1]"].iter()
+        .any(|host| origin == format!("http://{host}:{port}"))
+}
+
+///Compares in constant time, so that the response time tells nothing about how much of the token was right.
+///Comparing the digests also hides the length of the token.
+fn token_matches(provided: &str, token: &str) -> bool {
+    let provided = Sha256::digest(provided);
+    let token = Sha256::digest(token);
+    provided.iter().zip(token.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn authorize(State(state): State<ServerState>, request: Request, next: Next) -> Response {
+    let config = state.config();
+    //Browsers send the Origin with every cross-site POST. Without this check any website could control the counters.
+    if let Some(origin) = request.headers().get(header::ORIGIN) {
+        if !origin.to_str().is_ok_and(|origin| is_own_origin(origin, config.port)) {
+            log::warn!("Rejected a request to {} from the foreign origin {origin:?}", request.uri());
+            return (StatusCode::FORBIDDEN, "Requests from other websites are not allowed").into_response();
+        }
+    }
+    let token = config.token;
+    if token.is_empty() {
+        return next.run(request).await;
+    }
+    let provided = request.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if provided.is_some_and(|provided| token_matches(provided, &token)) {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "Missing or wrong bearer token",
+        ).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct TimeBody {
+    seconds: f64,
+}
+
+async fn apply(state: &ServerState, name: String, operation: Operation) -> Response {
+    match state.commands.request(Arc::from(name), operation).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(err),
+    }
+}
+
+pub(super) fn error_response(err: CommandError) -> Response {
+    let status = match err {
+        CommandError::UnknownCounter(_) => StatusCode::NOT_FOUND,
+        CommandError::NotAdjustable(_) | CommandError::NotTimed(_) => StatusCode::CONFLICT,
+        CommandError::NotListening => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, err.to_string()).into_response()
+}
+
+async fn start(State(state): State<ServerState>, Path(name): Path<String>) -> Response {
+    apply(&state, name, Operation::Start).await
+}
+async fn stop(State(state): State<ServerState>, Path(name): Path<String>) -> Response {
+    apply(&state, name, Operation::Stop).await
+}
+async fn reset(State(state): State<ServerState>, Path(name): Path<String>) -> Response {
+    apply(&state, name, Operation::Reset).await
+}
+async fn add(State(state): State<ServerState>, Path(name): Path<String>, Json(body): Json<TimeBody>) -> Response {
+    apply(&state, name, Operation::Add(seconds_to_nanos(body.seconds))).await
+}
+async fn set(State(state): State<ServerState>, Path(name): Path<String>, Json(body): Json<TimeBody>) -> Response {
+    apply(&state, name, Operation::Set(seconds_to_nanos(body.seconds))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origins() {
+        assert!(is_own_origin("http://localhost:8787", 8787));
+        assert!(is_own_origin("http://127.0.0.1:8787", 8787));
+        assert!(is_own_origin("http://[::1]:8787", 8787));
+        assert!(!is_own_origin("http://localhost:8788", 8787));
+        assert!(!is_own_origin("https://example.com", 8787));
+        assert!(!is_own_origin("http://localhost.example.com:8787", 8787));
+        assert!(!is_own_origin("null", 8787));
+    }
+
+    #[test]
+    fn tokens() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("Secret", "secret"));
+        assert!(!token_matches("secre", "secret"));
+        assert!(!token_matches("secrets", "secret"));
+        assert!(!token_matches("", "secret"));
+    }
+}
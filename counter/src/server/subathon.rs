@@ -0,0 +1,368 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use egui::Widget;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::app::command::{CommandError, Operation};
+use crate::counter_or_timer::{format_nanos, seconds_to_nanos, Mode, NANOS_IN_SECOND};
+use crate::server::{control, ServerState};
+
+///How many adjustments are kept for the ui
+const LOG_LEN: usize = 100;
+
+///Every adjustment the webhook made, newest last.
+pub(crate) type AdjustmentLog = Arc<Mutex<VecDeque<String>>>;
+
+///Adds time to a Timer, when events (subs, bits, donations, …) are posted to `/api/webhook/subathon`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct SubathonConfig {
+    ///Name of the Timer, that gets the time
+    pub counter: String,
+    ///The Timer is never pushed above this many seconds. 0 means no limit.
+    pub max_seconds: f64,
+    ///The first matching rule decides, how much time an event adds
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct Rule {
+    pub name: String,
+    ///All of these have to match the event
+    pub conditions: Vec<Condition>,
+    ///Json pointer to a number in the event (e.g. `/amount`). Empty means the event counts once.
+    pub amount: String,
+    ///How much of the amount gives `seconds`
+    pub per: f64,
+    pub seconds: f64,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            conditions: vec![Condition::default()],
+            amount: String::new(),
+            per: 1.,
+            seconds: 60.,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct Condition {
+    ///Json pointer into the event (e.g. `/type`)
+    pub pointer: String,
+    pub equals: String,
+}
+
+impl Condition {
+    fn matches(&self, event: &Value) -> bool {
+        match event.pointer(&self.pointer) {
+            Some(Value::String(value)) => *value == self.equals,
+            //Numbers and booleans
+            Some(value) => serde_json::from_str::<Value>(&self.equals).is_ok_and(|equals| equals == *value),
+            None => false,
+        }
+    }
+}
+
+impl Rule {
+    ///The seconds this rule adds for the event, if it matches it.
+    fn seconds_for(&self, event: &Value) -> Option<f64> {
+        if !self.conditions.iter().all(|condition| condition.matches(event)) {
+            return None;
+        }
+        if self.amount.is_empty() {
+            return Some(self.seconds);
+        }
+        let amount = match event.pointer(&self.amount)? {
+            Value::Number(number) => number.as_f64()?,
+            Value::String(number) => number.trim().parse().ok()?,
+            _ => return None,
+        };
+        let per = if self.per > 0. { self.per } else { 1. };
+        Some(amount / per * self.seconds)
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut keep = true;
+        ui.horizontal(|ui|{
+            ui.label("Rule:");
+            ui.add(egui::TextEdit::singleline(&mut self.name).hint_text("e.g. tier1 sub").desired_width(120.));
+            if ui.button("Delete Rule").clicked() {
+                keep = false;
+            }
+        });
+        let mut delete = None;
+        for (i, condition) in self.conditions.iter_mut().enumerate() {
+            ui.horizontal(|ui|{
+                ui.label("If");
+                ui.add(egui::TextEdit::singleline(&mut condition.pointer).hint_text("/type").desired_width(100.));
+                ui.label("is");
+                ui.add(egui::TextEdit::singleline(&mut condition.equals).hint_text("sub").desired_width(100.));
+                if ui.small_button("x").clicked() {
+                    delete = Some(i);
+                }
+            });
+        }
+        if let Some(i) = delete {
+            self.conditions.remove(i);
+        }
+        if ui.button("Add Condition").clicked() {
+            self.conditions.push(Condition::default());
+        }
+        ui.horizontal(|ui|{
+            ui.label("Add");
+            egui::DragValue::new(&mut self.seconds).suffix("s").ui(ui);
+            ui.label("per");
+            egui::DragValue::new(&mut self.per).clamp_range(0.01..=f64::MAX).ui(ui);
+            ui.add(egui::TextEdit::singleline(&mut self.amount).hint_text("event").desired_width(100.))
+                .on_hover_text("A json pointer to the amount (e.g. /amount for bits). Leave empty to add the time once per event.");
+        });
+        keep
+    }
+}
+
+impl SubathonConfig {
+    ///Returns the name of the first matching rule and the seconds it adds.
+    fn rule_for(&self, event: &Value) -> Option<(&str, f64)> {
+        self.rules.iter()
+            .find_map(|rule| Some((rule.name.as_str(), rule.seconds_for(event)?)))
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, log: &AdjustmentLog) {
+        ui.horizontal(|ui|{
+            ui.label("Timer:");
+            ui.add(egui::TextEdit::singleline(&mut self.counter).hint_text("name of a Timer"));
+        });
+        ui.horizontal(|ui|{
+            ui.label("Maximum:");
+            egui::DragValue::new(&mut self.max_seconds).clamp_range(0..=i64::MAX).suffix("s").ui(ui)
+                .on_hover_text("Events never push the Timer above this. 0 means no limit.");
+        });
+        ui.label("Post events as json to /api/webhook/subathon, e.g. {\"type\": \"bits\", \"amount\": 100}. The first matching rule adds the time.");
+        self.rules.retain_mut(|rule| {
+            ui.separator();
+            rule.ui(ui)
+        });
+        if ui.button("Add Rule").clicked() {
+            self.rules.push(Rule::default());
+        }
+        ui.separator();
+        ui.label("Adjustments:");
+        egui::ScrollArea::vertical().max_height(120.).stick_to_bottom(true).show(ui, |ui|{
+            for entry in log.lock().unwrap_or_else(|err| err.into_inner()).iter() {
+                ui.label(entry);
+            }
+        });
+    }
+}
+
+fn log_adjustment(log: &AdjustmentLog, entry: String) {
+    log::info!("Subathon: {entry}");
+    let entry = format!("{} {entry}", chrono::Local::now().format("%H:%M:%S"));
+    let mut log = log.lock().unwrap_or_else(|err| err.into_inner());
+    if log.len() >= LOG_LEN {
+        log.pop_front();
+    }
+    log.push_back(entry);
+}
+
+#[derive(Serialize)]
+struct Adjustment {
+    ///None, if no rule matched
+    rule: Option<String>,
+    added_seconds: f64,
+    capped: bool,
+}
+
+///Applies the first matching rule to the configured Timer.
+pub(super) async fn webhook(State(state): State<ServerState>, Json(event): Json<Value>) -> Response {
+    let config = state.config().subathon;
+    let Some((rule, seconds)) = config.rule_for(&event) else {
+        log_adjustment(&state.subathon_log, format!("No rule matched {event}"));
+        return Json(Adjustment{rule: None, added_seconds: 0., capped: false}).into_response();
+    };
+    let Some(status) = state.registry.find(&config.counter).map(|status| status.borrow().clone()) else {
+        let err = CommandError::UnknownCounter(Arc::from(config.counter.as_str()));
+        log_adjustment(&state.subathon_log, format!("Rule '{rule}' failed: {err}"));
+        return control::error_response(err);
+    };
+    if status.mode != Mode::Timer {
+        let err = format!("The Counter '{}' is not a Timer", config.counter);
+        log_adjustment(&state.subathon_log, format!("Rule '{rule}' failed: {err}"));
+        return (StatusCode::CONFLICT, err).into_response();
+    }
+    let wanted = seconds_to_nanos(seconds);
+    let max = if config.max_seconds > 0. { seconds_to_nanos(config.max_seconds) } else { i64::MAX };
+    let nanos = match state.commands.request(Arc::from(config.counter.as_str()), Operation::AddCapped { nanos: wanted, max }).await {
+        Ok(nanos) => nanos,
+        Err(err) => {
+            log_adjustment(&state.subathon_log, format!("Rule '{rule}' failed: {err}"));
+            return control::error_response(err);
+        }
+    };
+    let capped = nanos < wanted;
+    let added = format_nanos(nanos);
+    if capped {
+        log_adjustment(&state.subathon_log, format!("Rule '{rule}' added {added} to '{}' (capped from {})", config.counter, format_nanos(wanted)));
+    } else {
+        log_adjustment(&state.subathon_log, format!("Rule '{rule}' added {added} to '{}'", config.counter));
+    }
+    Json(Adjustment{rule: Some(rule.to_string()), added_seconds: nanos as f64 / NANOS_IN_SECOND as f64, capped}).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::watch;
+    use crate::app::command::CommandChannel;
+    use crate::app::registry::Registry;
+    use crate::counter_or_timer::{CounterId, CounterTimer, Status};
+    use crate::server::{router, ServerConfig, ServerState};
+    use super::*;
+
+    fn config() -> SubathonConfig {
+        SubathonConfig {
+            counter: "Timer".to_string(),
+            max_seconds: 60.,
+            rules: vec![
+                Rule {
+                    name: "bits".to_string(),
+                    conditions: vec![Condition { pointer: "/type".to_string(), equals: "bits".to_string() }],
+                    amount: "/amount".to_string(),
+                    per: 100.,
+                    seconds: 10.,
+                },
+                Rule {
+                    name: "sub".to_string(),
+                    conditions: vec![Condition { pointer: "/type".to_string(), equals: "sub".to_string() }],
+                    amount: String::new(),
+                    per: 1.,
+                    seconds: 4.,
+                },
+            ],
+        }
+    }
+
+    ///Serves the api, with a stopped counter applying the commands in place of the app.
+    async fn serve(mode: Mode, start_seconds: i64) -> SocketAddr {
+        serve_named("Timer", mode, start_seconds, AdjustmentLog::default()).await
+    }
+
+    ///Serves a single counter called `name`. The webhook adds time to "Timer".
+    async fn serve_named(name: &str, mode: Mode, start_seconds: i64, subathon_log: AdjustmentLog) -> SocketAddr {
+        let registry = Registry::default();
+        registry.publish(vec![watch::channel(Status { name: Arc::from(name), mode, ..Status::default() }).1]);
+        let mut channel = CommandChannel::default();
+        let state = ServerState {
+            registry,
+            commands: channel.sender(),
+            config: Arc::new(std::sync::RwLock::new(ServerConfig { subathon: config(), ..ServerConfig::default() })),
+            subathon_log,
+            shutdown: watch::channel(()).1,
+        };
+        tokio::spawn(async move {
+            let mut timer = CounterTimer::new(CounterId(0), Arc::from("Timer"), Default::default(), Default::default());
+            timer.set_start_value(start_seconds * NANOS_IN_SECOND);
+            while let Some(command) = channel.recv().await {
+                let Operation::AddCapped { nanos, max } = command.operation else {
+                    panic!("Unexpected {command:?}");
+                };
+                let _ = command.reply.unwrap().send(Ok(timer.add_time_capped(nanos, max).unwrap()));
+            }
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(state)).await });
+        addr
+    }
+
+    ///A minimal http client. Returns the status code and the body.
+    async fn post(addr: SocketAddr, body: &str) -> (u16, String) {
+        post_with(addr, "", body).await
+    }
+
+    ///`headers` are extra header lines, each ending in `\r\n`.
+    async fn post_with(addr: SocketAddr, headers: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("POST /api/webhook/subathon HTTP/1.1\r\nHost: localhost\r\n{headers}Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len());
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, body.to_string())
+    }
+
+    fn json(body: &str) -> Value {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rules() {
+        let addr = serve(Mode::Timer, 0).await;
+        let (status, body) = post(addr, r#"{"type": "bits", "amount": 250}"#).await;
+        assert_eq!(status, 200);
+        assert_eq!(json(&body), serde_json::json!({"rule": "bits", "added_seconds": 25.0, "capped": false}));
+        let (status, body) = post(addr, r#"{"type": "follow"}"#).await;
+        assert_eq!(status, 200);
+        assert_eq!(json(&body), serde_json::json!({"rule": null, "added_seconds": 0.0, "capped": false}));
+    }
+
+    ///Events arriving at the same time can't push the Timer over the maximum together.
+    #[tokio::test]
+    async fn burst_is_capped() {
+        let addr = serve(Mode::Timer, 50).await;
+        let responses = futures_util::future::join_all((0..5).map(|_| post(addr, r#"{"type": "sub"}"#))).await;
+        let mut added = 0.;
+        let mut capped = 0;
+        for (status, body) in responses {
+            assert_eq!(status, 200);
+            let body = json(&body);
+            added += body["added_seconds"].as_f64().unwrap();
+            capped += usize::from(body["capped"] == true);
+        }
+        assert_eq!(added, 10.);
+        assert_eq!(capped, 3);
+    }
+
+    #[tokio::test]
+    async fn not_a_timer() {
+        let log = AdjustmentLog::default();
+        let addr = serve_named("Timer", Mode::Counter, 0, log.clone()).await;
+        let (status, _) = post(addr, r#"{"type": "sub"}"#).await;
+        assert_eq!(status, 409);
+        let log = log.lock().unwrap();
+        assert!(log.back().unwrap().ends_with("Rule 'sub' failed: The Counter 'Timer' is not a Timer"), "{log:?}");
+    }
+
+    #[tokio::test]
+    async fn unknown_counter() {
+        let log = AdjustmentLog::default();
+        let addr = serve_named("Break", Mode::Timer, 0, log.clone()).await;
+        let (status, _) = post(addr, r#"{"type": "sub"}"#).await;
+        assert_eq!(status, 404);
+        let log = log.lock().unwrap();
+        assert!(log.back().unwrap().ends_with("Rule 'sub' failed: There is no Counter named 'Timer'"), "{log:?}");
+    }
+
+    ///A website, that the streamer has open, must not be able to add time.
+    #[tokio::test]
+    async fn foreign_origin() {
+        let addr = serve(Mode::Timer, 0).await;
+        let (status, _) = post_with(addr, "Origin: https://example.com\r\n", r#"{"type": "sub"}"#).await;
+        assert_eq!(status, 403);
+        let (status, _) = post_with(addr, &format!("Origin: http://localhost:{}\r\n", crate::server::DEFAULT_PORT), r#"{"type": "sub"}"#).await;
+        assert_eq!(status, 200);
+    }
+}